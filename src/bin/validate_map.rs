@@ -0,0 +1,50 @@
+use raycaster_rs::map::Map;
+use raycaster_rs::texture::TextureManager;
+use raycaster_rs::validate::validate;
+use std::env;
+use std::path::Path;
+use std::process::ExitCode;
+
+// Checks maps for mistakes before they're played:
+// `cargo run --bin validate_map -- level.map other.tmx ...`
+// Exits with a failure status if any map fails to load or has errors, so it can run in CI.
+fn main() -> ExitCode {
+    let paths: Vec<String> = env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("usage: validate_map <map file>...");
+        return ExitCode::from(2);
+    }
+    let texture_manager = match TextureManager::new() {
+        Ok(texture_manager) => texture_manager,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut failed = false;
+    for path in &paths {
+        let path = Path::new(path);
+        let (map, warnings) = match Map::open(path) {
+            Ok(opened) => opened,
+            Err(e) => {
+                println!("{}", e);
+                failed = true;
+                continue;
+            }
+        };
+        let mut report = validate(&map, &texture_manager);
+        // What the import left out comes first, it can explain the rest
+        report.warnings.splice(0..0, warnings);
+        for error in &report.errors {
+            println!("{}: error: {}", path.display(), error);
+        }
+        for warning in &report.warnings {
+            println!("{}: warning: {}", path.display(), warning);
+        }
+        println!("{}: {} errors, {} warnings", path.display(), report.errors.len(), report.warnings.len());
+        failed |= !report.errors.is_empty();
+    }
+
+    if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS }
+}
@@ -0,0 +1,76 @@
+use crate::ray::WallSide;
+use std::ops::BitOr;
+
+// What a cell is, as bits so map files can store them as one number
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CellFlags(pub u8);
+
+impl CellFlags {
+    pub const NONE: CellFlags = CellFlags(0);
+    // Stops the player and rays
+    pub const SOLID: CellFlags = CellFlags(1);
+    // Hides what's behind it. Solid cells without it are fences, grates and windows.
+    pub const BLOCKS_SIGHT: CellFlags = CellFlags(2);
+    pub const DOOR: CellFlags = CellFlags(4);
+    // Shows up on the minimap as an ordinary wall, so secret doors give nothing away
+    pub const SECRET: CellFlags = CellFlags(8);
+    // Hurts whoever stands in it
+    pub const DAMAGING: CellFlags = CellFlags(16);
+
+    pub fn contains(self, other: CellFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: CellFlags) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: CellFlags) {
+        self.0 &= !other.0;
+    }
+}
+
+impl BitOr for CellFlags {
+    type Output = CellFlags;
+
+    fn bitor(self, other: CellFlags) -> CellFlags {
+        CellFlags(self.0 | other.0)
+    }
+}
+
+// Everything the map knows about one cell
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cell {
+    // Wall type, 0 for an open cell. It's the texture of every face without its own, and
+    // what mirrors and per-type wall heights are keyed by.
+    pub wall: u8,
+    // Texture per face in WallSide order (north, south, east, west), 0 to use the wall type's
+    pub faces: [u8; 4],
+    // Flat texture ids. A ceiling of 0 is open to the sky.
+    pub floor: u8,
+    pub ceiling: u8,
+    // 0 (pitch black) to 255 (full daylight)
+    pub light: u8,
+    pub flags: CellFlags,
+}
+
+impl Cell {
+    // An open cell, or a wall that stops the player and hides what's behind it
+    pub fn new(wall: u8, floor: u8, ceiling: u8, light: u8) -> Self {
+        let flags = if wall > 0 { CellFlags::SOLID | CellFlags::BLOCKS_SIGHT } else { CellFlags::NONE };
+        Cell { wall, faces: [0; 4], floor, ceiling, light, flags }
+    }
+
+    pub fn set_face_texture(&mut self, side: WallSide, texture: u8) {
+        self.faces[face_index(side)] = texture;
+    }
+}
+
+pub fn face_index(side: WallSide) -> usize {
+    match side {
+        WallSide::North => 0,
+        WallSide::South => 1,
+        WallSide::East => 2,
+        WallSide::West => 3,
+    }
+}
@@ -0,0 +1,102 @@
+use crate::portal::Face;
+use std::collections::{HashMap, VecDeque};
+
+// How many decals are kept at once; adding more removes the oldest
+pub const MAX_DECALS: usize = 128;
+
+// Something drawn on top of a wall face, like a bullet hole or a blood splat
+pub struct Decal {
+    // Where the middle of the decal is: across the face (0.0..1.0, the same as WallHit::texture_u)
+    // and up from the floor in world units
+    pub u: f64,
+    pub height: f64,
+    // Width and height in world units
+    pub size: f64,
+    pub texture_name: String,
+}
+
+// Decals by the wall face they're on, oldest first on each face
+pub struct Decals {
+    faces: HashMap<Face, Vec<Decal>>,
+    // Faces in the order decals were added to them, to find the oldest decal when over the cap
+    order: VecDeque<Face>,
+}
+
+impl Default for Decals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decals {
+    pub fn new() -> Self {
+        Decals { faces: HashMap::new(), order: VecDeque::new() }
+    }
+
+    pub fn add(&mut self, face: Face, decal: Decal) {
+        if self.order.len() >= MAX_DECALS
+            && let Some(oldest) = self.order.pop_front()
+            && let Some(decals) = self.faces.get_mut(&oldest)
+        {
+            decals.remove(0);
+            if decals.is_empty() {
+                self.faces.remove(&oldest);
+            }
+        }
+        self.faces.entry(face).or_default().push(decal);
+        self.order.push_back(face);
+    }
+
+    pub fn on_face(&self, face: &Face) -> &[Decal] {
+        self.faces.get(face).map_or(&[], |decals| decals.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::WallSide;
+
+    fn decal(height: f64) -> Decal {
+        Decal { u: 0.5, height, size: 3.0, texture_name: "decal_bullet_hole".to_string() }
+    }
+
+    fn heights(decals: &Decals, face: &Face) -> Vec<f64> {
+        decals.on_face(face).iter().map(|decal| decal.height).collect()
+    }
+
+    #[test]
+    fn oldest_decals_go_first_once_over_the_cap() {
+        let (a, b) = (Face::new(1, 1, WallSide::North), Face::new(2, 1, WallSide::East));
+        let mut decals = Decals::new();
+        // The first two land on face a, then the rest alternate between b and a
+        decals.add(a, decal(0.0));
+        decals.add(a, decal(1.0));
+        for i in 2..MAX_DECALS {
+            decals.add(if i % 2 == 0 { b } else { a }, decal(i as f64));
+        }
+        assert_eq!(decals.on_face(&a).len() + decals.on_face(&b).len(), MAX_DECALS);
+        assert_eq!(heights(&decals, &a)[..2], [0.0, 1.0]);
+
+        // Two more push out the two oldest, both on face a
+        decals.add(b, decal(1000.0));
+        decals.add(b, decal(1001.0));
+        assert_eq!(decals.on_face(&a).len() + decals.on_face(&b).len(), MAX_DECALS);
+        assert_eq!(heights(&decals, &a)[0], 3.0);
+        assert_eq!(heights(&decals, &b)[..1], [2.0]);
+        assert_eq!(heights(&decals, &b).last(), Some(&1001.0));
+    }
+
+    #[test]
+    fn faces_left_without_decals_are_dropped() {
+        let (a, b) = (Face::new(1, 1, WallSide::North), Face::new(2, 1, WallSide::East));
+        let mut decals = Decals::new();
+        decals.add(a, decal(0.0));
+        for i in 1..=MAX_DECALS {
+            decals.add(b, decal(i as f64));
+        }
+        assert!(decals.on_face(&a).is_empty());
+        assert!(!decals.faces.contains_key(&a));
+        assert_eq!(heights(&decals, &b)[0], 1.0);
+    }
+}
@@ -0,0 +1,152 @@
+// How long a door takes to slide fully open or closed, and how long it stays open
+const DOOR_SLIDE_TIME: f64 = 1.0;
+const DOOR_STAY_OPEN_TIME: f64 = 3.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DoorState {
+    Closed,
+    Opening,
+    Open,
+    Closing,
+}
+
+// Wolfenstein-style sliding door. The door panel sits in the middle of its cell and slides
+// sideways into the wall; `openness` is how much of it has slid away (0 closed, 1 open).
+pub struct Door {
+    pub state: DoorState,
+    pub openness: f64,
+    // True if the panel runs along the x axis (the door is seen from the north or south)
+    pub along_x: bool,
+    open_timer: f64,
+}
+
+impl Door {
+    pub fn new(along_x: bool) -> Self {
+        Door {
+            state: DoorState::Closed,
+            openness: 0.0,
+            along_x,
+            open_timer: 0.0,
+        }
+    }
+
+    // Player pressed use on the door
+    pub fn activate(&mut self) {
+        self.state = match self.state {
+            DoorState::Closed | DoorState::Closing => DoorState::Opening,
+            DoorState::Open | DoorState::Opening => DoorState::Closing,
+        };
+    }
+
+    // `occupied` keeps an open door from closing on whoever is standing in it
+    pub fn update(&mut self, delta_time: f64, occupied: bool) {
+        match self.state {
+            DoorState::Opening => {
+                self.openness += delta_time / DOOR_SLIDE_TIME;
+                if self.openness >= 1.0 {
+                    self.openness = 1.0;
+                    self.open_timer = 0.0;
+                    self.state = DoorState::Open;
+                }
+            }
+            DoorState::Open => {
+                self.open_timer += delta_time;
+                if self.open_timer >= DOOR_STAY_OPEN_TIME && !occupied {
+                    self.state = DoorState::Closing;
+                }
+            }
+            DoorState::Closing => {
+                if occupied {
+                    self.state = DoorState::Opening;
+                    return;
+                }
+                self.openness -= delta_time / DOOR_SLIDE_TIME;
+                if self.openness <= 0.0 {
+                    self.openness = 0.0;
+                    self.state = DoorState::Closed;
+                }
+            }
+            DoorState::Closed => {}
+        }
+    }
+
+    // `u` is the position across the door (0.0..1.0). The panel has slid away from the
+    // low end, so only the part at or beyond `openness` still blocks.
+    pub fn blocks_at(&self, u: f64) -> bool {
+        u >= self.openness
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_door() -> Door {
+        let mut door = Door::new(true);
+        door.activate();
+        door.update(DOOR_SLIDE_TIME, false);
+        door
+    }
+
+    #[test]
+    fn opens_stays_open_then_closes() {
+        let mut door = Door::new(true);
+        door.activate();
+        assert_eq!(door.state, DoorState::Opening);
+        door.update(DOOR_SLIDE_TIME / 2.0, false);
+        assert_eq!((door.state, door.openness), (DoorState::Opening, 0.5));
+        door.update(DOOR_SLIDE_TIME, false);
+        assert_eq!((door.state, door.openness), (DoorState::Open, 1.0));
+
+        door.update(DOOR_STAY_OPEN_TIME - 0.1, false);
+        assert_eq!(door.state, DoorState::Open);
+        door.update(0.2, false);
+        assert_eq!(door.state, DoorState::Closing);
+        door.update(DOOR_SLIDE_TIME / 2.0, false);
+        assert_eq!((door.state, door.openness), (DoorState::Closing, 0.5));
+        door.update(DOOR_SLIDE_TIME, false);
+        assert_eq!((door.state, door.openness), (DoorState::Closed, 0.0));
+    }
+
+    #[test]
+    fn use_turns_it_round_mid_slide() {
+        let mut door = Door::new(false);
+        door.activate();
+        door.update(DOOR_SLIDE_TIME / 4.0, false);
+        door.activate();
+        assert_eq!(door.state, DoorState::Closing);
+        door.activate();
+        assert_eq!(door.state, DoorState::Opening);
+
+        let mut door = open_door();
+        door.activate();
+        assert_eq!(door.state, DoorState::Closing);
+    }
+
+    #[test]
+    fn never_closes_on_someone_in_the_doorway() {
+        let mut door = open_door();
+        door.update(DOOR_STAY_OPEN_TIME * 10.0, true);
+        assert_eq!(door.state, DoorState::Open);
+        // Once they've left it closes straight away, the wait is over
+        door.update(0.0, false);
+        assert_eq!(door.state, DoorState::Closing);
+
+        // Stepping in while it closes sends it back open
+        door.update(DOOR_SLIDE_TIME / 2.0, false);
+        door.update(0.1, true);
+        assert_eq!((door.state, door.openness), (DoorState::Opening, 0.5));
+        door.update(DOOR_SLIDE_TIME / 2.0, true);
+        assert_eq!(door.state, DoorState::Open);
+    }
+
+    #[test]
+    fn only_the_part_left_blocks() {
+        let mut door = Door::new(true);
+        assert!(door.blocks_at(0.0));
+        door.activate();
+        door.update(DOOR_SLIDE_TIME / 4.0, false);
+        assert!(!door.blocks_at(0.2));
+        assert!(door.blocks_at(0.25) && door.blocks_at(0.9));
+    }
+}
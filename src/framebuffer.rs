@@ -0,0 +1,193 @@
+use crate::lighting::Ramp;
+use crate::texture::PixelTexture;
+use sdl3::pixels::PixelFormatEnum;
+use sdl3::surface::Surface;
+use std::path::Path;
+
+// SDL has no byte-order independent enum variant, so pick the packed format whose
+// in-memory layout is R, G, B, A on this machine.
+#[cfg(target_endian = "little")]
+pub const RGBA32: PixelFormatEnum = PixelFormatEnum::ABGR8888;
+#[cfg(target_endian = "big")]
+pub const RGBA32: PixelFormatEnum = PixelFormatEnum::RGBA8888;
+
+// Texture column `tex_x` stretched down a screen column over `height` rows from `top`, repeating
+// `repeat` times and lit by `ramp`
+pub struct TextureSlice<'a> {
+    pub tex_x: u32,
+    pub top: i32,
+    pub height: i32,
+    pub repeat: f64,
+    pub ramp: &'a Ramp,
+}
+
+// Plain CPU-side RGBA pixel buffer that the whole frame is drawn into. Nothing here touches a
+// window or GPU, so frames can be rendered on headless machines and handed to SDL only for display.
+pub struct FrameBuffer {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    // Drawing is limited to this rect (x0, y0, x1, y1), exclusive at the far end
+    clip: (i32, i32, i32, i32),
+}
+
+impl FrameBuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        FrameBuffer {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+            clip: (0, 0, width as i32, height as i32),
+        }
+    }
+
+    // Restricts drawing to a rect of the frame, or lifts the restriction with None
+    pub fn set_clip_rect(&mut self, rect: Option<(i32, i32, u32, u32)>) {
+        self.clip = match rect {
+            Some((x, y, w, h)) => (
+                x.max(0),
+                y.max(0),
+                (x + w as i32).min(self.width as i32),
+                (y + h as i32).min(self.height as i32),
+            ),
+            None => (0, 0, self.width as i32, self.height as i32),
+        };
+    }
+
+    pub fn clip_rect(&self) -> (i32, i32, u32, u32) {
+        let (x0, y0, x1, y1) = self.clip;
+        (x0, y0, (x1 - x0).max(0) as u32, (y1 - y0).max(0) as u32)
+    }
+
+    // Bytes per row, as SDL wants it when uploading the buffer
+    pub fn pitch(&self) -> usize {
+        self.width as usize * 4
+    }
+
+    pub fn clear(&mut self, color: (u8, u8, u8)) {
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[color.0, color.1, color.2, 255]);
+        }
+    }
+
+    // Writes a pixel, blending by its alpha. Fully transparent pixels and anything outside the clip rect are skipped.
+    pub fn put_pixel(&mut self, x: i32, y: i32, color: [u8; 4]) {
+        let (x0, y0, x1, y1) = self.clip;
+        if x < x0 || y < y0 || x >= x1 || y >= y1 || color[3] == 0 {
+            return;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        if color[3] == 255 {
+            self.pixels[i..i + 4].copy_from_slice(&color);
+        } else {
+            let alpha = color[3] as u32;
+            for (dst, src) in self.pixels[i..i + 3].iter_mut().zip(color) {
+                *dst = ((src as u32 * alpha + *dst as u32 * (255 - alpha)) / 255) as u8;
+            }
+            self.pixels[i + 3] = 255;
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, w: u32, h: u32, color: (u8, u8, u8)) {
+        let x0 = x.max(0);
+        let y0 = y.max(0);
+        let x1 = (x + w as i32).min(self.width as i32);
+        let y1 = (y + h as i32).min(self.height as i32);
+        for py in y0..y1 {
+            for px in x0..x1 {
+                self.put_pixel(px, py, [color.0, color.1, color.2, 255]);
+            }
+        }
+    }
+
+    // Multiplies the pixels in a rect by a colour, e.g. to tint a reflection
+    pub fn tint_rect(&mut self, x: i32, y: i32, w: u32, h: u32, color: (u8, u8, u8)) {
+        let (cx0, cy0, cx1, cy1) = self.clip;
+        let x0 = x.max(cx0);
+        let y0 = y.max(cy0);
+        let x1 = (x + w as i32).min(cx1);
+        let y1 = (y + h as i32).min(cy1);
+        for py in y0..y1 {
+            for px in x0..x1 {
+                let i = (py as usize * self.width as usize + px as usize) * 4;
+                for (dst, tint) in self.pixels[i..i + 3].iter_mut().zip([color.0, color.1, color.2]) {
+                    *dst = (*dst as u32 * tint as u32 / 255) as u8;
+                }
+            }
+        }
+    }
+
+    // Bresenham line, clipped per pixel
+    pub fn draw_line(&mut self, from: (i32, i32), to: (i32, i32), color: (u8, u8, u8)) {
+        let (mut x, mut y) = from;
+        let dx = (to.0 - x).abs();
+        let dy = -(to.1 - y).abs();
+        let sx = if x < to.0 { 1 } else { -1 };
+        let sy = if y < to.1 { 1 } else { -1 };
+        let mut err = dx + dy;
+        loop {
+            self.put_pixel(x, y, [color.0, color.1, color.2, 255]);
+            if x == to.0 && y == to.1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    // Scales the whole texture into the destination rect, lit through `ramp`
+    pub fn blit(&mut self, texture: &PixelTexture, x: i32, y: i32, w: u32, h: u32, ramp: &Ramp) {
+        if w == 0 || h == 0 {
+            return;
+        }
+        let x0 = x.max(0);
+        let y0 = y.max(0);
+        let x1 = (x + w as i32).min(self.width as i32);
+        let y1 = (y + h as i32).min(self.height as i32);
+        for py in y0..y1 {
+            let tex_y = ((py - y) as u64 * texture.height as u64 / h as u64) as u32;
+            for px in x0..x1 {
+                let tex_x = ((px - x) as u64 * texture.width as u64 / w as u64) as u32;
+                self.put_pixel(px, py, ramp.apply(texture.sample(tex_x, tex_y)));
+            }
+        }
+    }
+
+    // Stretches one column of the texture over `height` pixels starting at `top`
+    pub fn draw_texture_column(&mut self, texture: &PixelTexture, tex_x: u32, x: i32, top: i32, height: i32, ramp: &Ramp) {
+        self.draw_wall_column(texture, x, &TextureSlice { tex_x, top, height, repeat: 1.0, ramp });
+    }
+
+    // Like draw_texture_column, but the texture can repeat over the column. The repeats line up
+    // with the bottom, so a half-height wall shows the lower half of the texture.
+    pub fn draw_wall_column(&mut self, texture: &PixelTexture, x: i32, slice: &TextureSlice) {
+        let TextureSlice { tex_x, top, height, repeat, ramp } = *slice;
+        if height <= 0 || x < 0 || x >= self.width as i32 {
+            return;
+        }
+        let offset = (1.0 - repeat.fract()).fract();
+        // Only the visible part of the column needs sampling
+        let y_start = top.max(0);
+        let y_end = top.saturating_add(height).min(self.height as i32);
+        for y in y_start..y_end {
+            let v = ((y - top) as f64 / height as f64 * repeat + offset).fract();
+            let tex_y = (v * texture.height as f64) as u32;
+            self.put_pixel(x, y, ramp.apply(texture.sample(tex_x, tex_y)));
+        }
+    }
+
+    pub fn save_bmp(&self, path: &Path) -> Result<(), String> {
+        let mut data = self.pixels.clone();
+        let surface = Surface::from_data(&mut data, self.width, self.height, self.pitch() as u32, RGBA32.into())
+            .map_err(|e| e.to_string())?;
+        surface.save_bmp(path)
+            .map_err(|e| format!("Failed to save frame {}: {}", path.display(), e))
+    }
+}
@@ -0,0 +1,18 @@
+pub mod cell;
+pub mod decal;
+pub mod door;
+pub mod framebuffer;
+pub mod globals;
+pub mod lighting;
+pub mod map;
+pub mod map_file;
+pub mod player;
+pub mod portal;
+pub mod ray;
+pub mod raycaster;
+pub mod sky;
+pub mod sprite;
+pub mod texture;
+pub mod tiled;
+pub mod transform;
+pub mod validate;
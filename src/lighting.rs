@@ -0,0 +1,136 @@
+use crate::globals::MAX_DEPTH;
+
+// Number of precomputed light ramps, like DOOM's 32 colormaps
+pub const LIGHT_LEVELS: usize = 32;
+
+// Ambient light left at MAX_DEPTH, so far walls fade into fog without vanishing completely
+const MIN_LIGHT: f64 = 0.2;
+
+// How light falls off with distance from the viewer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Falloff {
+    None,
+    Linear,
+    Quadratic,
+    Exponential(f64), // density
+}
+
+impl Falloff {
+    // Cycles through the curves, for toggling at runtime
+    pub fn next(self) -> Self {
+        match self {
+            Falloff::None => Falloff::Linear,
+            Falloff::Linear => Falloff::Quadratic,
+            Falloff::Quadratic => Falloff::Exponential(3.0),
+            Falloff::Exponential(_) => Falloff::None,
+        }
+    }
+}
+
+// Per-channel lookup table blending a colour toward the fog colour by a fixed amount
+pub struct Ramp {
+    table: [[u8; 256]; 3],
+}
+
+impl Ramp {
+    fn new(light: f64, fog: (u8, u8, u8)) -> Self {
+        let mut table = [[0; 256]; 3];
+        for (channel, fog_value) in table.iter_mut().zip([fog.0, fog.1, fog.2]) {
+            for (value, out) in channel.iter_mut().enumerate() {
+                let blended = value as f64 * light + fog_value as f64 * (1.0 - light);
+                *out = blended.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+        Ramp { table }
+    }
+
+    pub fn apply(&self, color: [u8; 4]) -> [u8; 4] {
+        [
+            self.table[0][color[0] as usize],
+            self.table[1][color[1] as usize],
+            self.table[2][color[2] as usize],
+            color[3],
+        ]
+    }
+}
+
+// Dynamic light such as a torch, a lamp or a muzzle flash. Adds to the light level of
+// everything within `radius`, fading out toward the edge.
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight {
+    pub x: f64,
+    pub y: f64,
+    pub radius: f64,
+    pub intensity: f64,
+}
+
+impl PointLight {
+    pub fn new(x: f64, y: f64, radius: f64, intensity: f64) -> Self {
+        PointLight { x, y, radius, intensity }
+    }
+
+    pub fn contribution(&self, x: f64, y: f64) -> f64 {
+        let distance = ((x - self.x).powi(2) + (y - self.y).powi(2)).sqrt();
+        if distance >= self.radius {
+            return 0.0;
+        }
+        let falloff = 1.0 - distance / self.radius;
+        self.intensity * falloff * falloff
+    }
+}
+
+// Shared lighting model for walls, flats and sprites. Colours are blended toward the fog colour
+// as they get further away; with black fog this is plain darkening.
+pub struct Lighting {
+    pub falloff: Falloff,
+    pub fog_color: (u8, u8, u8),
+    // Dynamic lights for the current frame
+    pub lights: Vec<PointLight>,
+    ramps: Vec<Ramp>,
+}
+
+impl Lighting {
+    pub fn new(falloff: Falloff, fog_color: (u8, u8, u8)) -> Self {
+        Lighting { falloff, fog_color, lights: Vec::new(), ramps: build_ramps(fog_color) }
+    }
+
+    pub fn set_fog_color(&mut self, fog_color: (u8, u8, u8)) {
+        self.fog_color = fog_color;
+        self.ramps = build_ramps(fog_color);
+    }
+
+    // Light reaching the viewer from `distance` away, between MIN_LIGHT and 1.0
+    pub fn intensity(&self, distance: f64) -> f64 {
+        let t = (distance / MAX_DEPTH).clamp(0.0, 1.0);
+        let curve = match self.falloff {
+            Falloff::None => 1.0,
+            Falloff::Linear => 1.0 - t,
+            Falloff::Quadratic => (1.0 - t) * (1.0 - t),
+            Falloff::Exponential(density) => (-density * t).exp(),
+        };
+        MIN_LIGHT + (1.0 - MIN_LIGHT) * curve
+    }
+
+    pub fn ramp(&self, intensity: f64) -> &Ramp {
+        let level = (intensity.clamp(0.0, 1.0) * (LIGHT_LEVELS - 1) as f64).round() as usize;
+        &self.ramps[level]
+    }
+
+    // Full light for a surface point at (x, y): the light level of the cell it's in (0-255) plus
+    // any dynamic lights reaching it, faded with distance from the viewer
+    pub fn light_at(&self, distance: f64, cell_light: u8, x: f64, y: f64) -> f64 {
+        let dynamic: f64 = self.lights.iter().map(|light| light.contribution(x, y)).sum();
+        (cell_light as f64 / 255.0 + dynamic).min(1.0) * self.intensity(distance)
+    }
+
+    // Unlit ramp for things drawn on top of the view, like the weapon
+    pub fn full_bright(&self) -> &Ramp {
+        &self.ramps[LIGHT_LEVELS - 1]
+    }
+}
+
+fn build_ramps(fog_color: (u8, u8, u8)) -> Vec<Ramp> {
+    (0..LIGHT_LEVELS)
+        .map(|level| Ramp::new(level as f64 / (LIGHT_LEVELS - 1) as f64, fog_color))
+        .collect()
+}
@@ -1,26 +1,83 @@
-mod globals;
-mod map;
-mod player;
-mod ray;
-mod raycaster;
-mod texture;
-mod sprite;
-
 use sdl3::event::Event;
 use sdl3::keyboard::{Keycode, Scancode};
-use sdl3::pixels::Color;
-use sdl3::rect::Rect;
+use sdl3::pixels::{Color, PixelFormat};
+use sdl3::render::{FRect, ScaleMode};
+use std::env;
+use std::path::Path;
 use std::time::{Duration, Instant};
 
-use crate::globals::{WINDOW_WIDTH, WINDOW_HEIGHT, TILE_SIZE};
-use crate::map::Map;
-use crate::player::Player;
-use crate::ray::Ray;
-use crate::raycaster::Raycaster;
-use crate::texture::TextureManager;
-use crate::sprite::Sprite;
+use raycaster_rs::decal::Decal;
+use raycaster_rs::framebuffer::{FrameBuffer, RGBA32};
+use raycaster_rs::globals::{WINDOW_WIDTH, WINDOW_HEIGHT, RENDER_WIDTH, RENDER_HEIGHT, TILE_SIZE, FOG_COLOR};
+use raycaster_rs::lighting::PointLight;
+use raycaster_rs::map::Map;
+use raycaster_rs::player::Player;
+use raycaster_rs::portal::Face;
+use raycaster_rs::ray::Ray;
+use raycaster_rs::raycaster::{Raycaster, MAX_FOV_DEGREES, MIN_FOV_DEGREES};
+use raycaster_rs::texture::TextureManager;
+use raycaster_rs::sprite::Sprite;
 
 fn main() -> Result<(), String> {
+    let args: Vec<String> = env::args().collect();
+    let texture_manager = TextureManager::new()?;
+    
+    // `--map level.map` plays a map file instead of the built-in one, maps saved by Tiled are imported
+    let mut map = match args.iter().position(|arg| arg == "--map") {
+        Some(i) => {
+            let path = Path::new(args.get(i + 1).ok_or("--map needs a map file")?);
+            let (map, warnings) = Map::open(path)?;
+            for warning in &warnings {
+                eprintln!("{}: warning: {}", path.display(), warning);
+            }
+            map
+        }
+        None => Map::new(),
+    };
+    // `--save-map out.map` writes the map out as a map file and exits, a starting point for new levels
+    if let Some(i) = args.iter().position(|arg| arg == "--save-map") {
+        return map.save(Path::new(args.get(i + 1).ok_or("--save-map needs an output path")?));
+    }
+    let mut sprites: Vec<Sprite> = map.entities.iter()
+        .map(|entity| {
+            let mut sprite = Sprite::new(entity.x, entity.y, &entity.texture_name);
+            sprite.z = entity.z;
+            sprite.angle = entity.angle;
+            sprite.light = entity.light;
+            sprite
+        })
+        .collect();
+    let mut player = Player::new();
+    player.x = map.spawn.x;
+    player.y = map.spawn.y;
+    player.rotation_angle = map.spawn.angle;
+    let mut raycaster = Raycaster::new();
+    // `--resolution 320x200` (or `half`) renders at a lower resolution and scales up to the window,
+    // with black bars if its shape doesn't match. `--smooth` filters the scaling instead of keeping
+    // hard pixel edges.
+    let (render_width, render_height) = match args.iter().position(|arg| arg == "--resolution") {
+        Some(i) => parse_resolution(args.get(i + 1).ok_or("--resolution needs a size like 320x200")?)?,
+        None => (RENDER_WIDTH, RENDER_HEIGHT),
+    };
+    let scale_mode = if args.iter().any(|arg| arg == "--smooth") { ScaleMode::Linear } else { ScaleMode::Nearest };
+    let mut frame = FrameBuffer::new(render_width, render_height);
+    // `--fov 90` sets the field of view in degrees
+    if let Some(i) = args.iter().position(|arg| arg == "--fov") {
+        let degrees: f64 = args.get(i + 1)
+            .and_then(|value| value.parse().ok())
+            .ok_or("--fov needs an angle in degrees")?;
+        raycaster.fov = degrees.clamp(MIN_FOV_DEGREES, MAX_FOV_DEGREES).to_radians();
+    }
+
+    // `--headless out.bmp` renders the first frame to a file without opening a window
+    if let Some(i) = args.iter().position(|arg| arg == "--headless") {
+        let path = args.get(i + 1).ok_or("--headless needs an output path")?;
+        raycaster.lighting.lights = sprites.iter().filter_map(Sprite::emitted_light).collect();
+        raycaster.cast_all_rays(&player, &map, frame.width);
+        raycaster.render_frame(&mut frame, &texture_manager, &map, &player, &sprites);
+        return frame.save_bmp(Path::new(path));
+    }
+
     let sdl = sdl3::init().unwrap();
     let video = sdl.video().unwrap();
     
@@ -32,27 +89,47 @@ fn main() -> Result<(), String> {
     
     let mut canvas = window.into_canvas();
     let texture_creator = canvas.texture_creator();
-    let mut texture_manager = TextureManager::new(&texture_creator)?;
-    
-    let mut sprites = vec![
-        Sprite::new(5.5 * TILE_SIZE as f64, 3.5 * TILE_SIZE as f64, "enemy_caco_demon"),
-        Sprite::new(8.5 * TILE_SIZE as f64, 7.5 * TILE_SIZE as f64, "enemy_cyber_demon"),
-        Sprite::new(12.5 * TILE_SIZE as f64, 5.5 * TILE_SIZE as f64, "enemy_soldier"),
-    ];
+    let mut screen_texture = texture_creator
+        .create_texture_streaming(PixelFormat::from(RGBA32), frame.width, frame.height)
+        .map_err(|e| e.to_string())?;
+    screen_texture.set_scale_mode(scale_mode);
+    let (output_width, output_height) = canvas.output_size().map_err(|e| e.to_string())?;
+    let screen_rect = letterbox((frame.width, frame.height), (output_width, output_height));
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
     
     let mut event_pump = sdl.event_pump()
         .map_err(|e| e.to_string())?;
     
-    let map = Map::new();
-    let mut player = Player::new();
-    let mut raycaster = Raycaster::new();
     let mut last_frame_time = Instant::now();
+    let mut muzzle_flash = 0.0;
+    // Field of view when not zoomed in
+    let mut fov = raycaster.fov;
     
     'running: loop {
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } |
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'running,
+                Event::KeyDown { keycode: Some(Keycode::Space), .. } | Event::KeyDown { keycode: Some(Keycode::E), .. } => {
+                    map.use_door(player.x, player.y, player.rotation_angle, TILE_SIZE as f64);
+                }
+                Event::KeyDown { keycode: Some(Keycode::J), .. } => {
+                    player.jump();
+                }
+                Event::KeyDown { keycode: Some(Keycode::LeftBracket), .. } => {
+                    fov = (fov.to_degrees() - 5.0).max(MIN_FOV_DEGREES).to_radians();
+                }
+                Event::KeyDown { keycode: Some(Keycode::RightBracket), .. } => {
+                    fov = (fov.to_degrees() + 5.0).min(MAX_FOV_DEGREES).to_radians();
+                }
+                Event::KeyDown { keycode: Some(Keycode::L), .. } => {
+                    raycaster.lighting.falloff = raycaster.lighting.falloff.next();
+                }
+                Event::KeyDown { keycode: Some(Keycode::F), .. } => {
+                    // Toggle between plain darkening and a grey haze
+                    let fog_color = if raycaster.lighting.fog_color == FOG_COLOR { (90, 100, 110) } else { FOG_COLOR };
+                    raycaster.lighting.set_fog_color(fog_color);
+                }
                 _ => {}
             }
         }
@@ -62,6 +139,10 @@ fn main() -> Result<(), String> {
         
         player.turn_direction = 0;
         player.move_direction = 0;
+        player.pitch_direction = 0;
+        player.crouching = keyboard_state.is_scancode_pressed(Scancode::C);
+        // Holding Z zooms in by halving the field of view
+        raycaster.fov = if keyboard_state.is_scancode_pressed(Scancode::Z) { fov / 2.0 } else { fov };
         
         if keyboard_state.is_scancode_pressed(Scancode::Right) || keyboard_state.is_scancode_pressed(Scancode::D) {
             player.turn_direction = 1;
@@ -75,6 +156,16 @@ fn main() -> Result<(), String> {
         if keyboard_state.is_scancode_pressed(Scancode::Down) || keyboard_state.is_scancode_pressed(Scancode::S) {
             player.move_direction = -1;
         }
+        if keyboard_state.is_scancode_pressed(Scancode::PageUp) {
+            player.pitch_direction = 1;
+        }
+        if keyboard_state.is_scancode_pressed(Scancode::PageDown) {
+            player.pitch_direction = -1;
+        }
+        if keyboard_state.is_scancode_pressed(Scancode::LCtrl) && muzzle_flash <= 0.0 {
+            muzzle_flash = 0.1;
+            fire_hitscan(&mut map, &raycaster, &frame, &player, &sprites);
+        }
         
         // Update animations
         let frame_time = last_frame_time.elapsed();
@@ -82,32 +173,28 @@ fn main() -> Result<(), String> {
         for sprite in &mut sprites {
             sprite.update_animation(delta_time);
         }
+        muzzle_flash -= delta_time;
+        raycaster.time += delta_time as f64;
+
+        raycaster.lighting.lights = sprites.iter().filter_map(Sprite::emitted_light).collect();
+        if muzzle_flash > 0.0 {
+            raycaster.lighting.lights.push(PointLight::new(player.x, player.y, 6.0 * TILE_SIZE as f64, 1.0));
+        }
         
+        map.update_doors(delta_time as f64, player.x, player.y);
         player.update(&map);
-        raycaster.cast_all_rays(&player, &map);
+        raycaster.cast_all_rays(&player, &map, frame.width);
         
-        canvas.set_draw_color(Color::BLACK);
-        canvas.clear();
-        
-        raycaster.render(&mut canvas, &mut texture_manager, &map, &player); 
-        map.render(&mut canvas);
-        raycaster.render_sprites(&mut canvas, &mut texture_manager, &player, &sprites);
-        player.render(&mut canvas);
-        raycaster.render_all_rays(&mut canvas,&player);
-
-        if let Some(weapon_texture) = texture_manager.get_texture_mut("weapon") {
-            canvas.copy(
-                weapon_texture,
-                None,
-                Rect::new(
-                    (WINDOW_WIDTH as i32 / 2 - 100) as i32,
-                    (WINDOW_HEIGHT as i32 - 200) as i32,
-                    200,
-                    200
-                )
-            ).unwrap();// Yes I am unwrapping everything. Don't question it.
+        raycaster.render_frame(&mut frame, &texture_manager, &map, &player, &sprites);
+        // Standing somewhere that hurts tints the view red
+        let tile = TILE_SIZE as f64;
+        if map.is_damaging((player.x / tile) as usize, (player.y / tile) as usize) {
+            frame.tint_rect(0, 0, frame.width, frame.height, (255, 120, 120));
         }
-        
+
+        screen_texture.update(None, &frame.pixels, frame.pitch()).map_err(|e| e.to_string())?;
+        canvas.clear();
+        canvas.copy(&screen_texture, None, screen_rect).unwrap();// Yes I am unwrapping everything. Don't question it.
         canvas.present();
         
         // Frame rate control
@@ -121,5 +208,74 @@ fn main() -> Result<(), String> {
     Ok(())
 }
 
+// Hitscan shot straight ahead from the player. Leaves a bullet hole where it hits a wall, or a
+// blood splat if it went through an enemy on the way.
+fn fire_hitscan(map: &mut Map, raycaster: &Raycaster, frame: &FrameBuffer, player: &Player, sprites: &[Sprite]) {
+    let mut shot = Ray::new(player.rotation_angle);
+    shot.cast(player, map);
+    // The shot stops at the nearest wall that isn't see-through
+    let Some(hit) = shot.hit.filter(|hit| !hit.door && !map.redirects_rays(hit)) else {
+        return;
+    };
+
+    let tile = TILE_SIZE as f64;
+    let (dir_x, dir_y) = (player.rotation_angle.cos(), player.rotation_angle.sin());
+    let hit_enemy = sprites.iter()
+        .filter(|sprite| sprite.visible && sprite.texture_name.starts_with("enemy_"))
+        .any(|sprite| {
+            let (dx, dy) = (sprite.x - player.x, sprite.y - player.y);
+            let along = dx * dir_x + dy * dir_y;
+            let across = (dx * dir_y - dy * dir_x).abs();
+            along > 0.0 && along < hit.distance && across < tile / 4.0
+        });
+    let (texture_name, size) = if hit_enemy { ("decal_blood", 12.0) } else { ("decal_bullet_hole", 3.0) };
+
+    // Shots over the top of the wall don't leave a mark
+    let height = raycaster.aim_height(frame, player, hit.distance);
+    let wall_height = map.get_wall_height(hit.map_x, hit.map_y) * tile;
+    if !(size / 2.0..=wall_height - size / 2.0).contains(&height) {
+        return;
+    }
+    map.decals.add(
+        Face::new(hit.map_x, hit.map_y, hit.side),
+        Decal { u: hit.texture_u(), height, size, texture_name: texture_name.to_string() },
+    );
+}
+
+// Parses `WIDTHxHEIGHT`, or `half` for half the window size
+fn parse_resolution(value: &str) -> Result<(u32, u32), String> {
+    if value == "half" {
+        return Ok((WINDOW_WIDTH / 2, WINDOW_HEIGHT / 2));
+    }
+    let invalid = || format!("Invalid resolution '{}', expected something like 320x200", value);
+    let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+    let width: u32 = width.parse().map_err(|_| invalid())?;
+    let height: u32 = height.parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok((width, height))
+}
+
+// Where a frame goes in the window: scaled up as far as it fits without stretching, and centred
+fn letterbox(frame: (u32, u32), window: (u32, u32)) -> FRect {
+    let scale = (window.0 as f32 / frame.0 as f32).min(window.1 as f32 / frame.1 as f32);
+    let (width, height) = (frame.0 as f32 * scale, frame.1 as f32 * scale);
+    FRect::new((window.0 as f32 - width) / 2.0, (window.1 as f32 - height) / 2.0, width, height)
+}
+
 // TODO work on adding mouse inputs for player movement and rotation
-// TODO add a weapon system with animations and firing mechanics
\ No newline at end of file
+// TODO add a weapon system with animations and firing mechanics
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn letterbox_keeps_the_frame_shape() {
+        // 16:10 in a 3:2 window gets bars top and bottom, a square gets them at the sides
+        assert_eq!(letterbox((320, 200), (1200, 800)), FRect::new(0.0, 25.0, 1200.0, 750.0));
+        assert_eq!(letterbox((400, 400), (1200, 800)), FRect::new(200.0, 0.0, 800.0, 800.0));
+        assert_eq!(letterbox((600, 400), (1200, 800)), FRect::new(0.0, 0.0, 1200.0, 800.0));
+    }
+}
@@ -1,72 +1,333 @@
-use crate::globals::{MINI_MAP_SCALE_FACTOR, TILE_SIZE};
-use sdl3::rect::Rect;
-use sdl3::render::Canvas;
-use sdl3::video::Window;
+use crate::cell::{face_index, Cell, CellFlags};
+use crate::decal::Decals;
+use crate::door::Door;
+use crate::framebuffer::FrameBuffer;
+use crate::globals::{MINI_MAP_SCALE_FACTOR, TILE_SIZE, WINDOW_WIDTH};
+use crate::lighting::PointLight;
+use crate::portal::{pass_transform, Face};
+use crate::ray::{WallHit, WallSide};
+use crate::transform::Transform;
+use std::collections::HashMap;
+
+// Where the player starts, in world units, facing `angle` radians
+#[derive(Clone, Copy, Debug)]
+pub struct Spawn {
+    pub x: f64,
+    pub y: f64,
+    pub angle: f64,
+}
+
+// A sprite the map places at load time, positioned in world units like the sprite itself
+#[derive(Clone, Debug)]
+pub struct Entity {
+    pub texture_name: String,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    // Direction it faces in radians, which picks the rotation frame it shows
+    pub angle: f64,
+    // Light the entity gives off. As with sprites, its position is ignored.
+    pub light: Option<PointLight>,
+}
+
+impl Entity {
+    pub fn new(texture_name: &str, x: f64, y: f64) -> Self {
+        Entity { texture_name: texture_name.to_string(), x, y, z: 0.0, angle: 0.0, light: None }
+    }
+}
 
 pub struct Map {
-    pub grid: Vec<Vec<u8>>,
+    // Rows of cells. Rows can be different lengths. Flats share the wall_N texture set.
+    pub cells: Vec<Vec<Cell>>,
+    // How far open each door cell is, keyed by (col, row). The cell's wall type is the door's texture.
+    pub doors: HashMap<(usize, usize), Door>,
+    // Wall types that are mirrors, with the colour they tint their reflection
+    pub mirrors: HashMap<u8, (u8, u8, u8)>,
+    // Linked wall faces, both ways round. Rays and the player going into one come out of the other.
+    pub portals: HashMap<Face, Face>,
+    // Wall heights in stories (1.0 is one TILE_SIZE). Per-type defaults, with per-cell overrides
+    // on top. Anything not listed is one story tall. Change them with set_wall_height and
+    // set_cell_height, which keep max_wall_height up to date.
+    pub wall_heights: HashMap<u8, f64>,
+    pub cell_heights: HashMap<(usize, usize), f64>,
+    // At least as tall as every height above, rays check it at every wall they pass
+    max_height: f64,
+    // Bullet holes and the like on wall faces
+    pub decals: Decals,
+    pub spawn: Spawn,
+    pub entities: Vec<Entity>,
+}
+
+impl Default for Map {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Map {
+    // The built-in level, from the map file that ships with the game
     pub fn new() -> Self {
+        Map::parse(include_str!("resources/maps/default.map")).expect("the built-in map should parse")
+    }
+
+    // A map built from separate wall type, floor, ceiling and light layers shaped like `grid`. Walls
+    // are solid and block sight; doors, portals, heights, the start and entities are added afterwards.
+    pub fn with_layers(grid: &[Vec<u8>], floor: &[Vec<u8>], ceiling: &[Vec<u8>], light: &[Vec<u8>]) -> Self {
+        let layer = |layer: &[Vec<u8>], row: usize, col: usize, default: u8| {
+            layer.get(row).and_then(|values| values.get(col)).copied().unwrap_or(default)
+        };
+        let cells = grid.iter().enumerate()
+            .map(|(row, walls)| {
+                walls.iter().enumerate()
+                    .map(|(col, &wall)| Cell::new(wall, layer(floor, row, col, 0), layer(ceiling, row, col, 0), layer(light, row, col, 255)))
+                    .collect()
+            })
+            .collect();
         Map {
-            grid: vec![
-                vec![1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
-                vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-                vec![1, 0, 2, 2, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 3, 3, 3, 0, 0, 0, 0, 0, 1],
-                vec![1, 0, 2, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 4, 4, 0, 1],
-                vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 4, 4, 0, 1],
-                vec![1, 0, 0, 0, 0, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-                vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-                vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 5, 5, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-                vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 0, 5, 0, 0, 0, 1, 1, 1, 1, 0, 1],
-                vec![1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 0, 5, 0, 0, 0, 1, 0, 0, 1, 0, 1],
-                vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 5, 5, 0, 0, 0, 1, 0, 0, 1, 0, 1],
-                vec![1, 0, 0, 0, 0, 0, 2, 2, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 0, 1],
-                vec![1, 0, 0, 0, 0, 0, 2, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-                vec![1, 0, 3, 3, 0, 0, 2, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-                vec![1, 0, 3, 3, 0, 0, 2, 2, 2, 0, 0, 0, 0, 0, 0, 4, 4, 4, 4, 0, 0, 0, 0, 1],
-                vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 4, 0, 0, 0, 0, 1],
-                vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 4, 0, 0, 5, 5, 1],
-                vec![1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, 4, 4, 4, 0, 0, 5, 5, 1],
-                vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-                vec![1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
-            ],
+            cells,
+            doors: HashMap::new(),
+            mirrors: HashMap::new(),
+            portals: HashMap::new(),
+            wall_heights: HashMap::new(),
+            cell_heights: HashMap::new(),
+            max_height: 1.0,
+            decals: Decals::new(),
+            spawn: Spawn { x: 0.0, y: 0.0, angle: 0.0 },
+            entities: Vec::new(),
         }
     }
 
-    pub fn get_wall_texture_id(&self, x: usize, y: usize) -> u8 {
-        if y < self.grid.len() && x < self.grid[y].len() {
-            let wall_value = self.grid[y][x];
-            if wall_value > 0 {
-                wall_value // Use the actual wall value from the grid
-            } else {
-                1 // Default texture if somehow we get here
+    pub fn add_portal(&mut self, a: Face, b: Face) {
+        self.portals.insert(a, b);
+        self.portals.insert(b, a);
+    }
+
+    // The portal a ray ran into, as (entry, exit) faces
+    pub fn portal_at(&self, hit: &WallHit) -> Option<(Face, Face)> {
+        if hit.door {
+            return None;
+        }
+        let entry = Face::new(hit.map_x, hit.map_y, hit.side);
+        self.portals.get(&entry).map(|&exit| (entry, exit))
+    }
+
+    // Mirrors and portals don't stop rays, they send them on somewhere else
+    pub fn redirects_rays(&self, hit: &WallHit) -> bool {
+        self.portal_at(hit).is_some() || (!hit.door && self.is_mirror(hit.map_x, hit.map_y))
+    }
+
+    // If moving from one point to another steps into a portal, the transform that carries the
+    // move out of the linked face. A move can cross a column and a row boundary at once, so the
+    // boundaries are checked in the order the move crosses them; a diagonal step can go through
+    // the corner of a neighbouring cell before it reaches the portal. Moves are shorter than a tile.
+    pub fn portal_crossing(&self, from: (f64, f64), to: (f64, f64)) -> Option<Transform> {
+        let tile = TILE_SIZE as f64;
+        let (mut col, mut row) = ((from.0 / tile).floor() as i32, (from.1 / tile).floor() as i32);
+        let (to_col, to_row) = ((to.0 / tile).floor() as i32, (to.1 / tile).floor() as i32);
+
+        // Boundaries crossed as (how far along the move, column step, row step)
+        let mut crossings = Vec::with_capacity(2);
+        if to_col != col {
+            let edge = to_col.max(col) as f64 * tile;
+            crossings.push(((edge - from.0) / (to.0 - from.0), (to_col - col).signum(), 0));
+        }
+        if to_row != row {
+            let edge = to_row.max(row) as f64 * tile;
+            crossings.push(((edge - from.1) / (to.1 - from.1), 0, (to_row - row).signum()));
+        }
+        crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        for (_, step_col, step_row) in crossings {
+            col += step_col;
+            row += step_row;
+            if !self.in_bounds(col, row) {
+                return None;
             }
-        } else {
-            1 // Default texture for out of bounds
+            // The face of the cell being stepped into that looks back where the move came from
+            let side = match (step_col, step_row) {
+                (1, _) => WallSide::West,
+                (-1, _) => WallSide::East,
+                (_, 1) => WallSide::North,
+                _ => WallSide::South,
+            };
+            let entry = Face::new(col as usize, row as usize, side);
+            if let Some(exit) = self.portals.get(&entry) {
+                return Some(pass_transform(&entry, exit));
+            }
+            if self.is_solid(col as usize, row as usize) {
+                return None;
+            }
+        }
+        None
+    }
+
+    // Turns a cell into a door. The panel runs between whichever pair of opposite
+    // neighbours are walls, so the door sits in its frame.
+    pub fn add_door(&mut self, col: usize, row: usize, texture_id: u8) {
+        let cell = &mut self.cells[row][col];
+        cell.wall = texture_id;
+        cell.flags.insert(CellFlags::SOLID | CellFlags::BLOCKS_SIGHT | CellFlags::DOOR);
+        let wall_west = col > 0 && self.is_solid(col - 1, row);
+        let wall_east = self.in_bounds(col as i32 + 1, row as i32) && self.is_solid(col + 1, row);
+        self.doors.insert((col, row), Door::new(wall_west && wall_east));
+    }
+
+    pub fn door_at(&self, col: usize, row: usize) -> Option<&Door> {
+        self.doors.get(&(col, row))
+    }
+
+    pub fn update_doors(&mut self, delta_time: f64, player_x: f64, player_y: f64) {
+        let player_cell = ((player_x / TILE_SIZE as f64) as usize, (player_y / TILE_SIZE as f64) as usize);
+        for (&cell, door) in self.doors.iter_mut() {
+            door.update(delta_time, cell == player_cell);
         }
     }
 
+    // Opens or closes the door in the cell `reach` units in front of (x, y), if there is one
+    pub fn use_door(&mut self, x: f64, y: f64, angle: f64, reach: f64) {
+        let col = ((x + angle.cos() * reach) / TILE_SIZE as f64).floor();
+        let row = ((y + angle.sin() * reach) / TILE_SIZE as f64).floor();
+        if col < 0.0 || row < 0.0 {
+            return;
+        }
+        if let Some(door) = self.doors.get_mut(&(col as usize, row as usize)) {
+            door.activate();
+        }
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> Option<&Cell> {
+        self.cells.get(y).and_then(|row| row.get(x))
+    }
+
+    pub fn get_wall_texture_id(&self, x: usize, y: usize) -> u8 {
+        match self.cell(x, y) {
+            Some(cell) if cell.wall > 0 => cell.wall,
+            // Default texture for open cells and out of bounds
+            _ => 1,
+        }
+    }
+
+    // Texture of one face of a wall, which can differ from the rest of the wall
+    pub fn get_face_texture_id(&self, x: usize, y: usize, side: WallSide) -> u8 {
+        match self.cell(x, y).map(|cell| cell.faces[face_index(side)]) {
+            Some(texture) if texture > 0 => texture,
+            _ => self.get_wall_texture_id(x, y),
+        }
+    }
+
+    pub fn get_floor_texture_id(&self, x: usize, y: usize) -> u8 {
+        self.cell(x, y).map_or(0, |cell| cell.floor)
+    }
+
+    // 0 means open sky
+    pub fn get_ceiling_texture_id(&self, x: usize, y: usize) -> u8 {
+        self.cell(x, y).map_or(0, |cell| cell.ceiling)
+    }
+
+    // Anything outside the map counts as fully lit
+    pub fn get_light_level(&self, x: usize, y: usize) -> u8 {
+        self.cell(x, y).map_or(255, |cell| cell.light)
+    }
+
+    pub fn get_wall_height(&self, x: usize, y: usize) -> f64 {
+        if let Some(&height) = self.cell_heights.get(&(x, y)) {
+            return height;
+        }
+        let wall_type = self.cell(x, y).map_or(0, |cell| cell.wall);
+        self.wall_heights.get(&wall_type).copied().unwrap_or(1.0)
+    }
+
+    pub fn set_wall_height(&mut self, wall_type: u8, height: f64) {
+        self.wall_heights.insert(wall_type, height);
+        self.max_height = self.max_height.max(height);
+    }
+
+    pub fn set_cell_height(&mut self, col: usize, row: usize, height: f64) {
+        self.cell_heights.insert((col, row), height);
+        self.max_height = self.max_height.max(height);
+    }
+
+    // No wall on the map is taller than this, in stories. Rays go on past lower walls
+    // for as long as something this tall could still show over them.
+    pub fn max_wall_height(&self) -> f64 {
+        self.max_height
+    }
+
+    pub fn in_bounds(&self, col: i32, row: i32) -> bool {
+        row >= 0 && (row as usize) < self.cells.len() && col >= 0 && (col as usize) < self.cells[row as usize].len()
+    }
+
+    // Cell-space counterpart of has_wall_at, used by the ray caster. Doors are never solid
+    // cells, the ray caster handles them itself.
+    pub fn is_solid(&self, col: usize, row: usize) -> bool {
+        let flags = self.cells[row][col].flags;
+        flags.contains(CellFlags::SOLID) && !flags.contains(CellFlags::DOOR)
+    }
+
+    pub fn is_see_through(&self, col: usize, row: usize) -> bool {
+        !self.cells[row][col].flags.contains(CellFlags::BLOCKS_SIGHT)
+    }
+
+    // Makes every wall of a type see-through, for textures with transparent holes (fences,
+    // grates, windows). Rays keep going through them and everything behind shows through the holes.
+    pub fn set_see_through(&mut self, wall_type: u8) {
+        for cell in self.cells.iter_mut().flatten().filter(|cell| cell.wall == wall_type) {
+            cell.flags.remove(CellFlags::BLOCKS_SIGHT);
+        }
+    }
+
+    pub fn is_mirror(&self, col: usize, row: usize) -> bool {
+        self.mirrors.contains_key(&self.cells[row][col].wall)
+    }
+
+    pub fn mirror_tint(&self, col: usize, row: usize) -> Option<(u8, u8, u8)> {
+        self.mirrors.get(&self.cells[row][col].wall).copied()
+    }
+
+    // Cells that hurt whoever stands in them. Anything outside the map is harmless.
+    pub fn is_damaging(&self, col: usize, row: usize) -> bool {
+        self.cell(col, row).is_some_and(|cell| cell.flags.contains(CellFlags::DAMAGING))
+    }
+
     pub fn has_wall_at(&self, x: f64, y: f64) -> bool {
-        let col = (x / TILE_SIZE as f64) as usize;
-        let row = (y / TILE_SIZE as f64) as usize;
-       
-        if row < self.grid.len() && col < self.grid[0].len() {
-            self.grid[row][col] > 0
+        let col = (x / TILE_SIZE as f64).floor();
+        let row = (y / TILE_SIZE as f64).floor();
+
+        // Rows can be different lengths, so the bounds are checked against the row itself
+        if self.in_bounds(col as i32, row as i32) {
+            let (col, row) = (col as usize, row as usize);
+            if let Some(door) = self.door_at(col, row) {
+                // Only the part of the doorway the panel still covers blocks
+                let along = if door.along_x { x } else { y };
+                return door.blocks_at((along / TILE_SIZE as f64).fract());
+            }
+            self.cells[row][col].flags.contains(CellFlags::SOLID)
         } else {
             true // Treat out of bounds as walls
         }
     }
 
-    pub fn render(&self, canvas: &mut Canvas<Window>) {
-        for (i, row) in self.grid.iter().enumerate() {
-            for (j, &tile) in row.iter().enumerate() {
-                let tile_x = ((j as f64) * TILE_SIZE as f64 * MINI_MAP_SCALE_FACTOR) as i32;
-                let tile_y = ((i as f64) * TILE_SIZE as f64 * MINI_MAP_SCALE_FACTOR) as i32;
+    pub fn render(&self, frame: &mut FrameBuffer) {
+        let scale = mini_map_scale(frame);
+        for (i, row) in self.cells.iter().enumerate() {
+            for (j, cell) in row.iter().enumerate() {
+                let tile = cell.wall;
+                let tile_x = ((j as f64) * TILE_SIZE as f64 * scale) as i32;
+                let tile_y = ((i as f64) * TILE_SIZE as f64 * scale) as i32;
+                // Secret doors look like the wall around them until they're opened
+                let door = self.door_at(j, i)
+                    .filter(|door| !cell.flags.contains(CellFlags::SECRET) || door.openness > 0.0);
                
                 let color = if tile == 0 {
-                    (240, 240, 240) // Light gray for floor
+                    if cell.flags.contains(CellFlags::DAMAGING) {
+                        (240, 170, 170) // Pink for floors that hurt
+                    } else {
+                        (240, 240, 240) // Light gray for floor
+                    }
+                } else if let Some(door) = door {
+                    // Doors fade from brown to floor colour as they open
+                    let open = door.openness;
+                    ((140.0 + 100.0 * open) as u8, (90.0 + 150.0 * open) as u8, (40.0 + 200.0 * open) as u8)
                 } else {
                     // Different colors for different wall types
                     match tile {
@@ -75,19 +336,54 @@ impl Map {
                         3 => (100, 150, 100), // Greenish
                         4 => (100, 100, 150), // Bluish
                         5 => (150, 150, 100), // Yellowish
+                        6 => (180, 180, 200), // Light blue for the grate
+                        7 => (200, 230, 240), // Pale cyan for mirrors
+                        8 => (200, 80, 40),   // Orange for lava
                         _ => (80, 80, 80),     // Default dark
                     }
                 };
                
-                canvas.set_draw_color(color);
-                canvas.fill_rect(Rect::new(
+                frame.fill_rect(
                     tile_x,
                     tile_y,
-                    (TILE_SIZE as f64 * MINI_MAP_SCALE_FACTOR - 1.0) as u32,
-                    (TILE_SIZE as f64 * MINI_MAP_SCALE_FACTOR - 1.0) as u32
-                )).unwrap();
+                    (TILE_SIZE as f64 * scale - 1.0) as u32,
+                    (TILE_SIZE as f64 * scale - 1.0) as u32,
+                    color
+                );
             
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// MINI_MAP_SCALE_FACTOR is meant for a window-sized frame, so shrink the minimap along with
+// lower render resolutions
+pub fn mini_map_scale(frame: &FrameBuffer) -> f64 {
+    MINI_MAP_SCALE_FACTOR * frame.width as f64 / WINDOW_WIDTH as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world(col: f64, row: f64) -> (f64, f64) {
+        (col * TILE_SIZE as f64, row * TILE_SIZE as f64)
+    }
+
+    #[test]
+    fn portal_crossing_straight_into_the_face() {
+        let map = Map::new();
+        // The portal is the north face of (10, 19), on the bottom edge of the map
+        assert!(map.portal_crossing(world(10.5, 18.98), world(10.5, 19.02)).is_some());
+        assert!(map.portal_crossing(world(9.5, 18.98), world(9.5, 19.02)).is_none());
+    }
+
+    #[test]
+    fn portal_crossing_diagonal_step_takes_the_face_it_crosses() {
+        let map = Map::new();
+        // Crosses into column 10 first, then down through the portal's north face
+        assert!(map.portal_crossing(world(9.97, 18.95), world(10.03, 19.01)).is_some());
+        // Crosses into row 19 first, which is the wall next to the portal
+        assert!(map.portal_crossing(world(9.97, 18.99), world(10.01, 19.03)).is_none());
+    }
+}
@@ -0,0 +1,524 @@
+use crate::globals::TILE_SIZE;
+use crate::cell::{face_index, Cell, CellFlags};
+use crate::lighting::PointLight;
+use crate::map::{Entity, Map, Spawn};
+use crate::portal::Face;
+use crate::ray::WallSide;
+use crate::tiled::is_tiled_map;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+// Text map files. Blank lines and anything after a '#' are ignored, the rest is split into
+// sections that each start with a `[name]` header:
+//
+//   [grid]      one line per row of whitespace separated wall ids, 0 for open cells
+//   [floor]     floor texture ids, same shape as the grid (optional, defaults to 4)
+//   [ceiling]   ceiling texture ids, 0 for open sky (optional, defaults to all sky)
+//   [light]     light levels 0..255 (optional, defaults to full daylight)
+//   [flags]     per cell flags, added up: 1 solid, 2 blocks sight, 8 secret, 16 damaging (optional,
+//               defaults to 3 for walls and 0 for open cells). Doors get theirs from [cells].
+//   [walls]     per wall type settings, one per line:
+//                 see_through <id>
+//                 mirror <id> <red> <green> <blue>
+//                 height <id> <stories>
+//   [cells]     per cell settings, one per line:
+//                 height <col> <row> <stories>
+//                 door <col> <row> <texture id>
+//                 portal <col> <row> <side> <col> <row> <side>    side is north, south, east or west.
+//                                                                  Both faces are on this map, portals
+//                                                                  can't lead to another map.
+//                 face <col> <row> <side> <texture id>            one face drawn with its own texture
+//   [player]    start <x> <y> <angle in degrees>
+//   [entities]  <texture name> <x> <y> [z <height>] [facing <angle in degrees>] [light <radius> <intensity>]
+//
+// Positions, heights and radii are in tiles, so 2.5 is the middle of the third cell.
+
+const SECTIONS: [&str; 9] = ["grid", "floor", "ceiling", "light", "flags", "walls", "cells", "player", "entities"];
+
+// Flat texture used when a map has no floor layer
+pub const DEFAULT_FLOOR: u8 = 4;
+
+fn error_at(line: usize, column: usize, message: &str) -> String {
+    format!("{}:{}: {}", line, column, message)
+}
+
+// The words on one line of a map file, each with the 1-based column it starts at
+struct Words<'a> {
+    line: usize,
+    words: Vec<(usize, &'a str)>,
+    next: usize,
+    // Column just past the end of the line, where missing words are reported
+    end: usize,
+}
+
+impl<'a> Words<'a> {
+    fn new(line: usize, text: &'a str) -> Self {
+        let mut words = Vec::new();
+        let mut start: Option<(usize, usize)> = None;
+        let mut length = 0;
+        for (column, (byte, c)) in text.char_indices().enumerate() {
+            length = column + 1;
+            if c.is_whitespace() {
+                if let Some((from, from_column)) = start.take() {
+                    words.push((from_column + 1, &text[from..byte]));
+                }
+            } else if start.is_none() {
+                start = Some((byte, column));
+            }
+        }
+        if let Some((from, from_column)) = start {
+            words.push((from_column + 1, &text[from..]));
+        }
+        Words { line, words, next: 0, end: length + 1 }
+    }
+
+    fn error(&self, column: usize, message: &str) -> String {
+        error_at(self.line, column, message)
+    }
+
+    fn remaining(&self) -> usize {
+        self.words.len() - self.next
+    }
+
+    fn word(&mut self, what: &str) -> Result<(usize, &'a str), String> {
+        let word = self.words.get(self.next).copied()
+            .ok_or_else(|| self.error(self.end, &format!("expected {}", what)))?;
+        self.next += 1;
+        Ok(word)
+    }
+
+    fn value<T: FromStr>(&mut self, what: &str) -> Result<T, String> {
+        let (column, word) = self.word(what)?;
+        word.parse().map_err(|_| self.error(column, &format!("expected {}, found '{}'", what, word)))
+    }
+
+    // A cell position, which has to be on the map
+    fn cell(&mut self, map: &Map) -> Result<(usize, usize), String> {
+        let column = self.words.get(self.next).map_or(self.end, |&(column, _)| column);
+        let col: usize = self.value("a column")?;
+        let row: usize = self.value("a row")?;
+        if !map.in_bounds(col as i32, row as i32) {
+            return Err(self.error(column, &format!("cell ({}, {}) is outside the map", col, row)));
+        }
+        Ok((col, row))
+    }
+
+    fn side(&mut self) -> Result<WallSide, String> {
+        let (column, word) = self.word("a side")?;
+        match word {
+            "north" => Ok(WallSide::North),
+            "south" => Ok(WallSide::South),
+            "east" => Ok(WallSide::East),
+            "west" => Ok(WallSide::West),
+            _ => Err(self.error(column, &format!("expected north, south, east or west, found '{}'", word))),
+        }
+    }
+
+    // Anything left over on the line is a mistake
+    fn finish(&self) -> Result<(), String> {
+        match self.words.get(self.next) {
+            Some(&(column, word)) => Err(self.error(column, &format!("unexpected '{}'", word))),
+            None => Ok(()),
+        }
+    }
+}
+
+struct Section<'a> {
+    // Line of the section header
+    line: usize,
+    lines: Vec<Words<'a>>,
+}
+
+fn read_row(words: &mut Words, what: &str) -> Result<Vec<u8>, String> {
+    (0..words.remaining()).map(|_| words.value(what)).collect()
+}
+
+// A per-cell layer, which has to be the same shape as the grid
+fn read_layer(section: Option<Section>, name: &str, grid: &[Vec<u8>], default: u8, what: &str) -> Result<Vec<Vec<u8>>, String> {
+    let Some(section) = section else {
+        return Ok(grid.iter().map(|row| vec![default; row.len()]).collect());
+    };
+    if section.lines.len() != grid.len() {
+        return Err(error_at(section.line, 1, &format!("[{}] has {} rows but the grid has {}", name, section.lines.len(), grid.len())));
+    }
+    section.lines.into_iter().zip(grid).map(|(mut words, grid_row)| {
+        let row = read_row(&mut words, what)?;
+        if row.len() != grid_row.len() {
+            let column = words.words.get(grid_row.len()).map_or(words.end, |&(column, _)| column);
+            return Err(words.error(column, &format!("expected {} values to match the grid, found {}", grid_row.len(), row.len())));
+        }
+        Ok(row)
+    }).collect()
+}
+
+fn side_name(side: WallSide) -> &'static str {
+    match side {
+        WallSide::North => "north",
+        WallSide::South => "south",
+        WallSide::East => "east",
+        WallSide::West => "west",
+    }
+}
+
+// Keeps saved numbers short, positions don't need more than a thousandth of a tile
+fn short(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
+
+fn write_rows(text: &mut String, name: &str, rows: &[Vec<u8>]) {
+    text.push_str(&format!("\n[{}]\n", name));
+    for row in rows {
+        let values: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+        text.push_str(&values.join(" "));
+        text.push('\n');
+    }
+}
+
+impl Map {
+    // Reads a map file, or imports it if it was saved by Tiled. Only imports have warnings.
+    pub fn open(path: &Path) -> Result<(Map, Vec<String>), String> {
+        if is_tiled_map(path) { Map::import_tiled(path) } else { Ok((Map::load(path)?, Vec::new())) }
+    }
+
+    // Reads a map file. Errors point at the file, line and column of the problem.
+    pub fn load(path: &Path) -> Result<Map, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read map {}: {}", path.display(), e))?;
+        Map::parse(&text).map_err(|e| format!("{}:{}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_text())
+            .map_err(|e| format!("Failed to save map {}: {}", path.display(), e))
+    }
+
+    // Parses the text of a map file. Errors start with "line:column:".
+    pub fn parse(text: &str) -> Result<Map, String> {
+        let tile = TILE_SIZE as f64;
+        let end_line = text.lines().count() + 1;
+
+        let mut sections: HashMap<&str, Section> = HashMap::new();
+        let mut current = None;
+        for (index, line) in text.lines().enumerate() {
+            let content = line.split('#').next().unwrap_or("");
+            let mut words = Words::new(index + 1, content);
+            let Some(&(column, first)) = words.words.first() else {
+                continue;
+            };
+            if first.starts_with('[') {
+                let name = first.strip_prefix('[')
+                    .and_then(|name| name.strip_suffix(']'))
+                    .filter(|name| SECTIONS.contains(name))
+                    .ok_or_else(|| words.error(column, &format!("unknown section '{}'", first)))?;
+                words.next = 1;
+                words.finish()?;
+                if sections.contains_key(name) {
+                    return Err(words.error(column, &format!("second [{}] section", name)));
+                }
+                sections.insert(name, Section { line: index + 1, lines: Vec::new() });
+                current = Some(name);
+            } else if let Some(section) = current.and_then(|name| sections.get_mut(name)) {
+                section.lines.push(words);
+            } else {
+                return Err(words.error(column, "expected a section header such as [grid]"));
+            }
+        }
+
+        let grid_section = sections.remove("grid")
+            .ok_or_else(|| error_at(end_line, 1, "missing [grid] section"))?;
+        let grid_line = grid_section.line;
+        let grid = grid_section.lines.into_iter()
+            .map(|mut words| read_row(&mut words, "a wall id"))
+            .collect::<Result<Vec<_>, _>>()?;
+        if grid.is_empty() {
+            return Err(error_at(grid_line, 1, "the grid has no rows"));
+        }
+        let floor = read_layer(sections.remove("floor"), "floor", &grid, DEFAULT_FLOOR, "a texture id")?;
+        let ceiling = read_layer(sections.remove("ceiling"), "ceiling", &grid, 0, "a texture id")?;
+        let light = read_layer(sections.remove("light"), "light", &grid, 255, "a light level")?;
+        let flags = sections.remove("flags")
+            .map(|section| read_layer(Some(section), "flags", &grid, 0, "flags"))
+            .transpose()?;
+
+        let mut see_through = Vec::new();
+        let mut mirrors = HashMap::new();
+        let mut wall_heights = Vec::new();
+        for mut words in sections.remove("walls").map_or_else(Vec::new, |section| section.lines) {
+            let (column, setting) = words.word("a wall setting")?;
+            match setting {
+                "see_through" => {
+                    see_through.push(words.value::<u8>("a wall id")?);
+                }
+                "mirror" => {
+                    let id: u8 = words.value("a wall id")?;
+                    let tint = (words.value("a red level")?, words.value("a green level")?, words.value("a blue level")?);
+                    mirrors.insert(id, tint);
+                }
+                "height" => {
+                    let id: u8 = words.value("a wall id")?;
+                    wall_heights.push((id, words.value::<f64>("a height in stories")?));
+                }
+                _ => return Err(words.error(column, &format!("unknown wall setting '{}', expected see_through, mirror or height", setting))),
+            }
+            words.finish()?;
+        }
+
+        let mut map = Map::with_layers(&grid, &floor, &ceiling, &light);
+        if let Some(flags) = flags {
+            for (cell, &bits) in map.cells.iter_mut().flatten().zip(flags.iter().flatten()) {
+                cell.flags = CellFlags(bits);
+                cell.flags.remove(CellFlags::DOOR);
+            }
+        }
+        for wall_type in see_through {
+            map.set_see_through(wall_type);
+        }
+        map.mirrors = mirrors;
+        for (wall_type, height) in wall_heights {
+            map.set_wall_height(wall_type, height);
+        }
+
+        // Doors look at their neighbours when they're added, so cells come after the whole grid is known
+        for mut words in sections.remove("cells").map_or_else(Vec::new, |section| section.lines) {
+            let (column, setting) = words.word("a cell setting")?;
+            match setting {
+                "height" => {
+                    let (col, row) = words.cell(&map)?;
+                    let height = words.value("a height in stories")?;
+                    map.set_cell_height(col, row, height);
+                }
+                "door" => {
+                    let (col, row) = words.cell(&map)?;
+                    let texture_column = words.words.get(words.next).map_or(words.end, |&(column, _)| column);
+                    let texture: u8 = words.value("a texture id")?;
+                    if texture == 0 {
+                        return Err(words.error(texture_column, "a door needs a texture id above 0"));
+                    }
+                    map.add_door(col, row, texture);
+                }
+                "portal" => {
+                    let (col, row) = words.cell(&map)?;
+                    let a = Face::new(col, row, words.side()?);
+                    let (col, row) = words.cell(&map)?;
+                    let b = Face::new(col, row, words.side()?);
+                    map.add_portal(a, b);
+                }
+                "face" => {
+                    let (col, row) = words.cell(&map)?;
+                    let side = words.side()?;
+                    let texture = words.value("a texture id")?;
+                    map.cells[row][col].set_face_texture(side, texture);
+                }
+                _ => return Err(words.error(column, &format!("unknown cell setting '{}', expected height, door, portal or face", setting))),
+            }
+            words.finish()?;
+        }
+
+        let player = sections.remove("player")
+            .ok_or_else(|| error_at(end_line, 1, "missing [player] section"))?;
+        let mut spawn = None;
+        for mut words in player.lines {
+            let (column, setting) = words.word("start")?;
+            if setting != "start" {
+                return Err(words.error(column, &format!("unknown player setting '{}', expected start", setting)));
+            }
+            if spawn.is_some() {
+                return Err(words.error(column, "the player already has a start"));
+            }
+            let x: f64 = words.value("an x position")?;
+            let y: f64 = words.value("a y position")?;
+            let angle: f64 = words.value("an angle in degrees")?;
+            words.finish()?;
+            spawn = Some(Spawn { x: x * tile, y: y * tile, angle: angle.to_radians() });
+        }
+        map.spawn = spawn.ok_or_else(|| error_at(player.line, 1, "[player] needs a start line"))?;
+
+        for mut words in sections.remove("entities").map_or_else(Vec::new, |section| section.lines) {
+            let (_, texture_name) = words.word("a texture name")?;
+            let x: f64 = words.value("an x position")?;
+            let y: f64 = words.value("a y position")?;
+            let mut entity = Entity::new(texture_name, x * tile, y * tile);
+            while words.remaining() > 0 {
+                let (column, option) = words.word("an option")?;
+                match option {
+                    "z" => entity.z = words.value::<f64>("a height")? * tile,
+                    "facing" => entity.angle = words.value::<f64>("an angle in degrees")?.to_radians(),
+                    "light" => {
+                        let radius: f64 = words.value("a light radius")?;
+                        let intensity = words.value("a light intensity")?;
+                        entity.light = Some(PointLight::new(0.0, 0.0, radius * tile, intensity));
+                    }
+                    _ => return Err(words.error(column, &format!("unknown entity option '{}', expected z, facing or light", option))),
+                }
+            }
+            map.entities.push(entity);
+        }
+
+        Ok(map)
+    }
+
+    // The map in the file format read by `parse`. Doors are saved closed and decals aren't saved.
+    pub fn to_text(&self) -> String {
+        let tile = TILE_SIZE as f64;
+        let mut text = String::from("# Raycaster map\n");
+        let layer = |value: fn(&Cell) -> u8| -> Vec<Vec<u8>> {
+            self.cells.iter().map(|row| row.iter().map(value).collect()).collect()
+        };
+        write_rows(&mut text, "grid", &layer(|cell| cell.wall));
+        write_rows(&mut text, "floor", &layer(|cell| cell.floor));
+        write_rows(&mut text, "ceiling", &layer(|cell| cell.ceiling));
+        write_rows(&mut text, "light", &layer(|cell| cell.light));
+        // Door lines set the door flag again
+        write_rows(&mut text, "flags", &layer(|cell| {
+            let mut flags = cell.flags;
+            flags.remove(CellFlags::DOOR);
+            flags.0
+        }));
+
+        // Hash maps are sorted so saving the same map twice gives the same file
+        text.push_str("\n[walls]\n");
+        let mut mirrors: Vec<_> = self.mirrors.iter().collect();
+        mirrors.sort();
+        for (id, (red, green, blue)) in mirrors {
+            text.push_str(&format!("mirror {} {} {} {}\n", id, red, green, blue));
+        }
+        let mut wall_heights: Vec<_> = self.wall_heights.iter().collect();
+        wall_heights.sort_by_key(|&(id, _)| id);
+        for (id, height) in wall_heights {
+            text.push_str(&format!("height {} {}\n", id, short(*height)));
+        }
+
+        text.push_str("\n[cells]\n");
+        let mut cell_heights: Vec<_> = self.cell_heights.iter().collect();
+        cell_heights.sort_by_key(|&(&(col, row), _)| (row, col));
+        for ((col, row), height) in cell_heights {
+            text.push_str(&format!("height {} {} {}\n", col, row, short(*height)));
+        }
+        let mut doors: Vec<_> = self.doors.keys().collect();
+        doors.sort_by_key(|&&(col, row)| (row, col));
+        for &(col, row) in doors {
+            text.push_str(&format!("door {} {} {}\n", col, row, self.cells[row][col].wall));
+        }
+        // Each portal is stored both ways round, but only written once
+        let mut portals: Vec<_> = self.portals.iter().collect();
+        portals.sort_by_key(|(face, _)| (face.row, face.col, side_name(face.side)));
+        let mut written = HashSet::new();
+        for (a, b) in portals {
+            if written.insert(*b) {
+                written.insert(*a);
+                text.push_str(&format!("portal {} {} {} {} {} {}\n", a.col, a.row, side_name(a.side), b.col, b.row, side_name(b.side)));
+            }
+        }
+        for (row, cells) in self.cells.iter().enumerate() {
+            for (col, cell) in cells.iter().enumerate() {
+                for side in [WallSide::North, WallSide::South, WallSide::East, WallSide::West] {
+                    let texture = cell.faces[face_index(side)];
+                    if texture > 0 {
+                        text.push_str(&format!("face {} {} {} {}\n", col, row, side_name(side), texture));
+                    }
+                }
+            }
+        }
+
+        text.push_str("\n[player]\n");
+        text.push_str(&format!("start {} {} {}\n", short(self.spawn.x / tile), short(self.spawn.y / tile), short(self.spawn.angle.to_degrees())));
+
+        text.push_str("\n[entities]\n");
+        for entity in &self.entities {
+            text.push_str(&format!("{} {} {}", entity.texture_name, short(entity.x / tile), short(entity.y / tile)));
+            if entity.z != 0.0 {
+                text.push_str(&format!(" z {}", short(entity.z / tile)));
+            }
+            if entity.angle != 0.0 {
+                text.push_str(&format!(" facing {}", short(entity.angle.to_degrees())));
+            }
+            if let Some(light) = entity.light {
+                text.push_str(&format!(" light {} {}", short(light.radius / tile), short(light.intensity)));
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A room with one open cell, followed by `extra` sections
+    fn small(extra: &str) -> String {
+        format!("[grid]\n1 1 1\n1 0 1\n1 1 1\n[player]\nstart 1.5 1.5 0\n{}", extra)
+    }
+
+    fn sorted<T: Ord + Copy>(keys: impl Iterator<Item = T>) -> Vec<T> {
+        let mut keys: Vec<T> = keys.collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn round_trip_keeps_everything() {
+        let mut map = Map::new();
+        map.cells[1][0].set_face_texture(WallSide::East, 5);
+        map.cells[18][1].flags.insert(CellFlags::DAMAGING);
+        map.cells[9][2].flags.insert(CellFlags::SECRET);
+        map.entities[1].angle = 90f64.to_radians();
+        let text = map.to_text();
+        let parsed = Map::parse(&text).unwrap();
+
+        assert_eq!(parsed.cells, map.cells);
+        assert!(parsed.cells[10][13].flags.contains(CellFlags::DOOR));
+        assert_eq!(sorted(parsed.doors.keys().copied()), sorted(map.doors.keys().copied()));
+        assert_eq!(parsed.portals, map.portals);
+        assert_eq!(parsed.mirrors, map.mirrors);
+        assert_eq!(parsed.wall_heights, map.wall_heights);
+        assert_eq!(parsed.cell_heights, map.cell_heights);
+        assert_eq!(parsed.max_wall_height(), 2.0);
+        assert_eq!((parsed.spawn.x, parsed.spawn.y, parsed.spawn.angle), (map.spawn.x, map.spawn.y, map.spawn.angle));
+        let entities = |map: &Map| -> Vec<String> { map.entities.iter().map(|entity| format!("{:?}", entity)).collect() };
+        assert_eq!(entities(&parsed), entities(&map));
+        assert_eq!(parsed.to_text(), text);
+    }
+
+    #[test]
+    fn bad_number() {
+        let text = "[grid]\n1 1 1\n1 x 1\n1 1 1\n[player]\nstart 1.5 1.5 0\n";
+        assert_eq!(Map::parse(text).err().unwrap(), "3:3: expected a wall id, found 'x'");
+    }
+
+    #[test]
+    fn row_of_the_wrong_length() {
+        let text = small("[floor]\n4 4 4\n4 4 4 4\n4 4 4\n");
+        assert_eq!(Map::parse(&text).err().unwrap(), "9:7: expected 3 values to match the grid, found 4");
+    }
+
+    #[test]
+    fn unknown_section() {
+        assert_eq!(Map::parse(&small("[wat]\n")).err().unwrap(), "7:1: unknown section '[wat]'");
+    }
+
+    #[test]
+    fn duplicate_section() {
+        assert_eq!(Map::parse(&small("\n  [player]\n")).err().unwrap(), "8:3: second [player] section");
+    }
+
+    #[test]
+    fn missing_player() {
+        let text = "[grid]\n1 1 1\n1 0 1\n1 1 1\n";
+        assert_eq!(Map::parse(text).err().unwrap(), "5:1: missing [player] section");
+    }
+
+    #[test]
+    fn entity_facing() {
+        let map = Map::parse(&small("[entities]\nenemy_soldier 1.5 1.5 facing 90 z 0.5\n")).unwrap();
+        assert_eq!(map.entities[0].angle, 90f64.to_radians());
+        assert_eq!(map.entities[0].z, 0.5 * TILE_SIZE as f64);
+    }
+
+    #[test]
+    fn cell_outside_the_map() {
+        assert_eq!(Map::parse(&small("[cells]\nheight 5 0 2\n")).err().unwrap(), "8:8: cell (5, 0) is outside the map");
+    }
+}
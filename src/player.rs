@@ -1,8 +1,7 @@
-use crate::globals::{MINI_MAP_SCALE_FACTOR, WINDOW_HEIGHT, WINDOW_WIDTH};
-use sdl3::render::Canvas;
-use sdl3::video::Window;
+use crate::framebuffer::FrameBuffer;
+use crate::globals::{TILE_SIZE, WINDOW_HEIGHT, WINDOW_WIDTH};
 use std::f64::consts::PI;
-use crate::map::Map;
+use crate::map::{mini_map_scale, Map};
 pub struct Player {
     pub x: f64,
     pub y: f64,
@@ -12,6 +11,27 @@ pub struct Player {
     pub rotation_angle: f64,
     pub move_speed: f64,
     pub rotation_speed: f64,
+    // Vertical look as a y-shear in window pixels, positive looks up (the horizon moves down)
+    pub pitch: f64,
+    pub pitch_direction: i8,
+    // Eye height above the floor in world units; half a wall when standing
+    pub eye_height: f64,
+    pub crouching: bool,
+    vertical_speed: f64,
+}
+
+// Eye heights and jump physics, per frame like the other speeds
+const STAND_EYE_HEIGHT: f64 = TILE_SIZE as f64 / 2.0;
+const CROUCH_EYE_HEIGHT: f64 = TILE_SIZE as f64 / 4.0;
+const JUMP_SPEED: f64 = 2.5;
+const GRAVITY: f64 = 0.2;
+const PITCH_SPEED: f64 = 8.0;
+const MAX_PITCH: f64 = WINDOW_HEIGHT as f64 / 2.0;
+
+impl Default for Player {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Player {
@@ -25,11 +45,41 @@ impl Player {
             rotation_angle: 0.0,
             move_speed: 2.5,
             rotation_speed: 2.0 * (PI / 180.0),
+            pitch: 0.0,
+            pitch_direction: 0,
+            eye_height: STAND_EYE_HEIGHT,
+            crouching: false,
+            vertical_speed: 0.0,
+        }
+    }
+
+    // Jumping is only possible from the ground
+    pub fn jump(&mut self) {
+        if self.eye_height <= self.ground_eye_height() {
+            self.vertical_speed = JUMP_SPEED;
         }
     }
 
+    fn ground_eye_height(&self) -> f64 {
+        if self.crouching { CROUCH_EYE_HEIGHT } else { STAND_EYE_HEIGHT }
+    }
+
     pub fn update(&mut self, map: &Map) {
         self.rotation_angle += self.turn_direction as f64 * self.rotation_speed;
+        self.pitch = (self.pitch + self.pitch_direction as f64 * PITCH_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+
+        // Fall back to the ground after a jump or when crouching, and ease back up when standing
+        let ground = self.ground_eye_height();
+        if self.vertical_speed != 0.0 || self.eye_height > ground {
+            self.eye_height += self.vertical_speed;
+            self.vertical_speed -= GRAVITY;
+            if self.eye_height <= ground {
+                self.eye_height = ground;
+                self.vertical_speed = 0.0;
+            }
+        } else {
+            self.eye_height = (self.eye_height + 1.0).min(ground);
+        }
 
         // Normalize angle to 0..2PI
         if self.rotation_angle < 0.0 {
@@ -40,32 +90,37 @@ impl Player {
         }
 
         let move_step = self.move_direction as f64 * self.move_speed;
-        if self.x + self.rotation_angle.cos() * move_step < 0.0 ||
-           self.x + self.rotation_angle.cos() * move_step > WINDOW_WIDTH as f64 ||
-           self.y + self.rotation_angle.sin() * move_step < 0.0 ||
-           self.y + self.rotation_angle.sin() * move_step > WINDOW_HEIGHT as f64 || map.has_wall_at( self.x + self.rotation_angle.cos() * move_step, self.y + self.rotation_angle.sin() * move_step) {
-            return; // Prevent moving out of bounds
+        let to = (self.x + self.rotation_angle.cos() * move_step, self.y + self.rotation_angle.sin() * move_step);
+        // Walking into a portal carries the player out of the linked face, turned the same way rays are
+        if let Some(pass) = map.portal_crossing((self.x, self.y), to) {
+            (self.x, self.y) = pass.apply(to.0, to.1);
+            self.rotation_angle = pass.apply_angle(self.rotation_angle);
+            return;
+        }
+        // Anything off the map counts as a wall, so this also keeps the player on the map
+        if map.has_wall_at(to.0, to.1) {
+            return;
         }
-        self.x += self.rotation_angle.cos() * move_step;
-        self.y += self.rotation_angle.sin() * move_step;
+        (self.x, self.y) = to;
     }
 
-    pub fn render(&self, canvas: &mut Canvas<Window>) {
-        canvas.set_draw_color((255, 0, 0));
+    pub fn render(&self, frame: &mut FrameBuffer) {
+        let scale = mini_map_scale(frame);
         // Scale the player's position for the minimap
-        let mini_x = (self.x * MINI_MAP_SCALE_FACTOR).round() as i32;
-        let mini_y = (self.y * MINI_MAP_SCALE_FACTOR).round() as i32;
-        canvas.draw_point((mini_x, mini_y)).unwrap();
+        let mini_x = (self.x * scale).round() as i32;
+        let mini_y = (self.y * scale).round() as i32;
+        frame.put_pixel(mini_x, mini_y, [255, 0, 0, 255]);
 
         // Draw direction line, also scaled
-        let line_length = 50.0 * MINI_MAP_SCALE_FACTOR;
+        let line_length = 50.0 * scale;
         let end_x = self.x + self.rotation_angle.cos() * 50.0;
         let end_y = self.y + self.rotation_angle.sin() * 50.0;
-        let mini_end_x = (end_x * MINI_MAP_SCALE_FACTOR).round() as i32;
-        let mini_end_y = (end_y * MINI_MAP_SCALE_FACTOR).round() as i32;
-        canvas.draw_line(
+        let mini_end_x = (end_x * scale).round() as i32;
+        let mini_end_y = (end_y * scale).round() as i32;
+        frame.draw_line(
             (mini_x, mini_y),
-            (mini_end_x, mini_end_y)
-        ).unwrap();
+            (mini_end_x, mini_end_y),
+            (255, 0, 0)
+        );
     }
 }
\ No newline at end of file
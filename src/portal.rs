@@ -0,0 +1,55 @@
+use crate::globals::TILE_SIZE;
+use crate::ray::WallSide;
+use crate::transform::Transform;
+use std::f64::consts::FRAC_PI_2;
+
+// One face of a wall cell: the side of cell (col, row) that faces `side`. A portal links two of
+// these on the same map; whatever goes into one comes out of the other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Face {
+    pub col: usize,
+    pub row: usize,
+    pub side: WallSide,
+}
+
+impl Face {
+    pub fn new(col: usize, row: usize, side: WallSide) -> Self {
+        Face { col, row, side }
+    }
+
+    // Unit vector pointing out of the wall
+    pub fn normal(&self) -> (f64, f64) {
+        match self.side {
+            WallSide::North => (0.0, -1.0),
+            WallSide::South => (0.0, 1.0),
+            WallSide::West => (-1.0, 0.0),
+            WallSide::East => (1.0, 0.0),
+        }
+    }
+
+    // Middle of the face in world units
+    pub fn centre(&self) -> (f64, f64) {
+        let tile = TILE_SIZE as f64;
+        let (nx, ny) = self.normal();
+        ((self.col as f64 + 0.5 + nx / 2.0) * tile, (self.row as f64 + 0.5 + ny / 2.0) * tile)
+    }
+
+    // The open cell the face looks into
+    pub fn front_cell(&self) -> (i32, i32) {
+        let (nx, ny) = self.normal();
+        (self.col as i32 + nx as i32, self.row as i32 + ny as i32)
+    }
+}
+
+// Takes positions and directions going into `entry` to where they come out of `exit`. Going in
+// against one face's normal comes out along the other's, turned by whole quarter turns, with the
+// middle of one face landing on the middle of the other.
+pub fn pass_transform(entry: &Face, exit: &Face) -> Transform {
+    let (in_x, in_y) = entry.normal();
+    let (out_x, out_y) = exit.normal();
+    let quarter_turns = (((-out_y).atan2(-out_x) - in_y.atan2(in_x)) / FRAC_PI_2).round() as i32;
+
+    let (entry_x, entry_y) = Transform::rotation(quarter_turns, (0.0, 0.0)).apply(entry.centre().0, entry.centre().1);
+    let (exit_x, exit_y) = exit.centre();
+    Transform::rotation(quarter_turns, (exit_x - entry_x, exit_y - entry_y))
+}
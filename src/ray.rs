@@ -1,6 +1,6 @@
-use crate::{globals::{MINI_MAP_SCALE_FACTOR, TILE_SIZE, WINDOW_HEIGHT, WINDOW_WIDTH}, map::Map, player::Player};
-use sdl3::render::Canvas;
-use sdl3::video::Window;
+use crate::{framebuffer::FrameBuffer, globals::TILE_SIZE, map::{mini_map_scale, Map}, player::Player};
+use crate::portal::pass_transform;
+use crate::transform::Transform;
 use std::f64::consts::PI;
 
 fn normalize_angle(angle: f64) -> f64 {
@@ -11,16 +11,104 @@ fn normalize_angle(angle: f64) -> f64 {
     normalized
 }
 
-fn distance_between(x1: f64, y1: f64, x2: f64, y2: f64) -> f64 {
-    ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt()
+// Which face of a map cell a ray struck. A ray travelling east enters a
+// cell through its west face, so `West` means "the wall faces west".
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WallSide {
+    North,
+    South,
+    East,
+    West,
 }
 
+// One wall surface a ray ran into
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WallHit {
+    pub x: f64,
+    pub y: f64,
+    // Perpendicular (fish-eye corrected) distance
+    pub distance: f64,
+    pub map_x: usize,
+    pub map_y: usize,
+    pub side: WallSide,
+    // Fraction (0.0..1.0) along the face that was hit, measured along the world x axis for
+    // north/south faces and along the world y axis for east/west faces.
+    pub tex_offset: f64,
+    // The hit is on a door panel in the middle of cell (map_x, map_y) rather than a cell face
+    pub door: bool,
+}
+
+impl WallHit {
+    // Horizontal texture coordinate (0.0..1.0) for the face that was hit. Faces looked at from the
+    // north or east run against the world axis on screen, so they're flipped to avoid mirrored textures.
+    pub fn texture_u(&self) -> f64 {
+        match self.side {
+            WallSide::North | WallSide::East => 1.0 - self.tex_offset,
+            WallSide::South | WallSide::West => self.tex_offset,
+        }
+    }
+
+    // The open cell in front of the face that was hit, i.e. the one the ray arrived from (or the
+    // door's own cell). Indices wrap around for faces on the map edge, which callers treat as out of bounds.
+    pub fn front_cell(&self) -> (usize, usize) {
+        if self.door {
+            return (self.map_x, self.map_y);
+        }
+        match self.side {
+            WallSide::North => (self.map_x, self.map_y.wrapping_sub(1)),
+            WallSide::South => (self.map_x, self.map_y + 1),
+            WallSide::West => (self.map_x.wrapping_sub(1), self.map_y),
+            WallSide::East => (self.map_x + 1, self.map_y),
+        }
+    }
+}
+
+// How many mirrors and portals a ray may go through before the last one is drawn flat
+pub const MAX_RAY_SEGMENTS: usize = 4;
+
+// The part of a ray after it bounced off a mirror or went through a portal
+#[derive(Debug, PartialEq)]
+pub struct RaySegment {
+    // Maps real positions on this part of the ray to where they appear to the viewer
+    pub transform: Transform,
+    // Same as on the ray itself, in real coordinates. Distances are measured along the whole
+    // path, so walls seen through mirrors and portals shrink with how far they really are from the viewer.
+    pub distance: f64,
+    pub hit: Option<WallHit>,
+    pub layers: Vec<WallHit>,
+    pub behind: Vec<WallHit>,
+    // Where this part of the ray starts and ends, for the minimap
+    pub start_x: f64,
+    pub start_y: f64,
+    pub end_x: f64,
+    pub end_y: f64,
+}
+
+impl RaySegment {
+    // Every wall on this part of the ray, in the order they're drawn
+    pub fn back_to_front(&self) -> impl Iterator<Item = &WallHit> {
+        self.behind.iter().rev().chain(&self.hit).chain(self.layers.iter().rev())
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub struct Ray {
     pub ray_angle: f64,
+    // Where the ray ended, on the wall that stopped it or the map edge
     pub wall_hit_x: f64,
     pub wall_hit_y: f64,
+    // Distance to the wall that stopped the ray, f64::MAX if the ray left the map. Nothing
+    // further away shows in this column.
     pub distance: f64,
-    pub color: u8,
+    // The first wall that blocks sight, or the mirror or portal face the ray went into
+    pub hit: Option<WallHit>,
+    // See-through walls in front of `hit`, nearest first
+    pub layers: Vec<WallHit>,
+    // Walls beyond `hit` that show over the top of it, nearest first. The ray goes on until the
+    // walls so far hide anything that could still be further along, or it meets a mirror or portal.
+    pub behind: Vec<WallHit>,
+    // If the ray stopped on a mirror or portal, where it went from there, one entry per mirror or portal
+    pub segments: Vec<RaySegment>,
 }
 
 impl Ray {
@@ -29,115 +117,374 @@ impl Ray {
             ray_angle: normalize_angle(angle),
             wall_hit_x: 0.0,
             wall_hit_y: 0.0,
-            distance: 0.0,
-            color: 255,
+            distance: f64::MAX,
+            hit: None,
+            layers: Vec::new(),
+            behind: Vec::new(),
+            segments: Vec::new(),
         }
     }
 
+    // Points a reused ray in a new direction; the old results are cleared by the next cast
+    pub fn set_angle(&mut self, angle: f64) {
+        self.ray_angle = normalize_angle(angle);
+    }
+
+    // Every wall the ray met before any mirror or portal, in the order they're drawn
+    pub fn back_to_front(&self) -> impl Iterator<Item = &WallHit> {
+        self.behind.iter().rev().chain(&self.hit).chain(self.layers.iter().rev())
+    }
+
     pub fn cast(&mut self, player: &Player, map: &Map) {
-        let is_facing_down = self.ray_angle > 0.0 && self.ray_angle < PI;
-        let is_facing_up = !is_facing_down;
-        let is_facing_right = self.ray_angle < 0.5 * PI || self.ray_angle > 1.5 * PI;
-        let is_facing_left = !is_facing_right;
-
-        // Horizontal wall check
-        let mut horizontal_hit = (0.0, 0.0);
-        let mut found_horizontal_wall = false;
-        let mut next_horizontal = (0.0, 0.0);
-        
-        // First intersection point
-        if is_facing_up {
-            next_horizontal.1 = (player.y / TILE_SIZE as f64).floor() * TILE_SIZE as f64 - 0.01;
-        } else {
-            next_horizontal.1 = (player.y / TILE_SIZE as f64).floor() * TILE_SIZE as f64 + TILE_SIZE as f64;
-        }
-        next_horizontal.0 = player.x + (next_horizontal.1 - player.y) / self.ray_angle.tan();
-
-        // Step size
-        let ya = if is_facing_up { -TILE_SIZE as f64 } else { TILE_SIZE as f64 };
-        let xa = ya / self.ray_angle.tan();
-        
-        let mut current = next_horizontal;
-        while current.0 >= 0.0 && current.0 < WINDOW_WIDTH as f64 && 
-              current.1 >= 0.0 && current.1 < WINDOW_HEIGHT as f64 {
-            if map.has_wall_at(current.0, current.1) {
-                horizontal_hit = current;
-                found_horizontal_wall = true;
+        // Fish-eye correction: walls are placed by distance from the camera plane, not the eye.
+        // It stays the same through mirrors and portals, as the path still looks like one straight line to the viewer.
+        let perpendicular = (player.rotation_angle - self.ray_angle).cos();
+        let tile = TILE_SIZE as f64;
+        let start_cell = ((player.x / tile).floor() as i32, (player.y / tile).floor() as i32);
+        let mut sight = Sight {
+            eye_height: player.eye_height,
+            start: 0.0,
+            perpendicular,
+            window_top: f64::NEG_INFINITY,
+            covered: f64::INFINITY,
+        };
+
+        self.layers.clear();
+        self.behind.clear();
+        let stop = trace(map, (player.x, player.y), start_cell, self.ray_angle, &mut sight, &mut self.layers, &mut self.behind);
+        self.hit = stop.hit;
+        self.wall_hit_x = player.x + self.ray_angle.cos() * stop.travelled;
+        self.wall_hit_y = player.y + self.ray_angle.sin() * stop.travelled;
+        self.distance = stop.distance;
+
+        // Keep going through mirrors and portals, carrying on from the far side in the new direction
+        self.segments.clear();
+        let mut transform = Transform::identity();
+        let mut angle = self.ray_angle;
+        sight.start = stop.travelled;
+        let mut through = stop.through;
+        while let Some(face) = through {
+            if self.segments.len() == MAX_RAY_SEGMENTS {
                 break;
             }
-            current.0 += xa;
-            current.1 += ya;
-        }
+            let (pass, start_cell) = if let Some((entry, exit)) = map.portal_at(&face) {
+                (pass_transform(&entry, &exit), exit.front_cell())
+            } else {
+                let (front_x, front_y) = face.front_cell();
+                (Transform::mirror(&face), (front_x as i32, front_y as i32))
+            };
+            angle = pass.apply_angle(angle);
+            let (start_x, start_y) = pass.apply(face.x, face.y);
+            transform = transform.after(&pass.inverse());
+            // What's beyond only shows inside the face
+            sight.window_top = sight.window_top.max(sight.top(map, &face));
 
-        // Vertical wall check
-        let mut vertical_hit = (0.0, 0.0);
-        let mut found_vertical_wall = false;
-        let mut next_vertical = (0.0, 0.0);
-        
-        if is_facing_left {
-            next_vertical.0 = (player.x / TILE_SIZE as f64).floor() * TILE_SIZE as f64 - 0.01;
-        } else {
-            next_vertical.0 = (player.x / TILE_SIZE as f64).floor() * TILE_SIZE as f64 + TILE_SIZE as f64;
+            let (mut layers, mut behind) = (Vec::new(), Vec::new());
+            let stop = trace(map, (start_x, start_y), start_cell, angle, &mut sight, &mut layers, &mut behind);
+            self.segments.push(RaySegment {
+                transform,
+                distance: stop.distance,
+                hit: stop.hit,
+                layers,
+                behind,
+                start_x,
+                start_y,
+                end_x: start_x + angle.cos() * stop.travelled,
+                end_y: start_y + angle.sin() * stop.travelled,
+            });
+            sight.start += stop.travelled;
+            through = stop.through;
         }
-        next_vertical.1 = player.y + (next_vertical.0 - player.x) * self.ray_angle.tan();
-
-        // Step size
-        let xa = if is_facing_left { -TILE_SIZE as f64 } else { TILE_SIZE as f64 };
-        let ya = xa * self.ray_angle.tan();
-        
-        let mut current = next_vertical;
-        while current.0 >= 0.0 && current.0 < WINDOW_WIDTH as f64 && 
-              current.1 >= 0.0 && current.1 < WINDOW_HEIGHT as f64 {
-            if map.has_wall_at(current.0, current.1) {
-                vertical_hit = current;
-                found_vertical_wall = true;
-                break;
-            }
-            current.0 += xa;
-            current.1 += ya;
+    }
+
+    pub fn render(&self, frame: &mut FrameBuffer, player: &Player) {
+        let scale = mini_map_scale(frame);
+        frame.draw_line(
+            ((player.x * scale) as i32, (player.y*scale) as i32),
+            ((self.wall_hit_x * scale) as i32, (self.wall_hit_y*scale) as i32),
+            (255, 0, 0)
+        );
+        for segment in &self.segments {
+            frame.draw_line(
+                ((segment.start_x * scale) as i32, (segment.start_y * scale) as i32),
+                ((segment.end_x * scale) as i32, (segment.end_y * scale) as i32),
+                (255, 128, 0)
+            );
         }
+    }
+}
 
-        // Calculate distances
-        let horz_distance = if found_horizontal_wall {
-            distance_between(player.x, player.y, horizontal_hit.0, horizontal_hit.1)
-        } else {
-            f64::MAX
-        };
+// How a stretch of ray is seen, for working out which walls along it can show and when nothing
+// further can. Heights on screen are kept as slopes, rows below the horizon per unit of
+// perpendicular distance, so they hold for any frame size and pitch.
+struct Sight {
+    eye_height: f64,
+    // How far the ray came before this stretch, and its fish-eye correction
+    start: f64,
+    perpendicular: f64,
+    // Top of the mirror or portal faces this stretch is seen through. Nothing above it shows.
+    window_top: f64,
+    // Top of the highest wall that blocks sight so far. Only what rises above it still shows.
+    covered: f64,
+}
 
-        let vert_distance = if found_vertical_wall {
-            distance_between(player.x, player.y, vertical_hit.0, vertical_hit.1)
-        } else {
-            f64::MAX
+impl Sight {
+    fn slope(&self, height: f64, distance: f64) -> f64 {
+        (self.eye_height - height) / distance
+    }
+
+    // Where the top of a wall lands on screen
+    fn top(&self, map: &Map, wall: &WallHit) -> f64 {
+        self.slope(map.get_wall_height(wall.map_x, wall.map_y) * TILE_SIZE as f64, wall.distance)
+    }
+
+    // True once nothing `distance` or further away can show: the walls so far cover the whole
+    // window, or even the tallest wall on the map would stay below their tops
+    fn hides_beyond(&self, map: &Map, distance: f64) -> bool {
+        let tallest = map.max_wall_height() * TILE_SIZE as f64;
+        // A wall taller than the eye shows most of itself when it's nearest; one lower than the
+        // eye keeps rising on screen with distance, towards the horizon
+        let highest = if tallest > self.eye_height { self.slope(tallest, distance) } else { 0.0 };
+        self.covered <= self.window_top || self.covered <= highest
+    }
+}
+
+// How a stretch of ray ended
+struct Stop {
+    // The first wall that blocks sight
+    hit: Option<WallHit>,
+    // How far the ray went, and the perpendicular distance to the wall it stopped at, f64::MAX if it left the map
+    travelled: f64,
+    distance: f64,
+    // The mirror or portal face the ray went into, if that's what stopped it and it shows
+    through: Option<WallHit>,
+}
+
+// Grid DDA: walk cell boundary to cell boundary along the ray, always stepping across
+// whichever of the next vertical or horizontal grid line is closer. Every cell the ray
+// passes through is visited exactly once, so it can't slip between two diagonal walls.
+//
+// The walk starts at `origin` inside (or on the edge of) `start_cell` and has already come
+// `sight.start` units, which hit distances include. The first wall that blocks sight is
+// returned, see-through walls in front of it go to `layers` and walls showing over its top go to
+// `behind`. Distances along the ray are measured from `origin`.
+fn trace(map: &Map, origin: (f64, f64), start_cell: (i32, i32), angle: f64, sight: &mut Sight, layers: &mut Vec<WallHit>, behind: &mut Vec<WallHit>) -> Stop {
+    let tile = TILE_SIZE as f64;
+    let (origin_x, origin_y) = origin;
+    let dir_x = angle.cos();
+    let dir_y = angle.sin();
+
+    let (mut map_x, mut map_y) = start_cell;
+
+    // Distance along the ray needed to cross one whole cell in x / y
+    let delta_x = if dir_x == 0.0 { f64::INFINITY } else { (tile / dir_x).abs() };
+    let delta_y = if dir_y == 0.0 { f64::INFINITY } else { (tile / dir_y).abs() };
+
+    // Distance along the ray to the first vertical / horizontal grid line
+    let (step_x, mut side_dist_x) = if dir_x < 0.0 {
+        (-1, (origin_x - map_x as f64 * tile) / -dir_x)
+    } else if dir_x > 0.0 {
+        (1, ((map_x + 1) as f64 * tile - origin_x) / dir_x)
+    } else {
+        (0, f64::INFINITY)
+    };
+    let (step_y, mut side_dist_y) = if dir_y < 0.0 {
+        (-1, (origin_y - map_y as f64 * tile) / -dir_y)
+    } else if dir_y > 0.0 {
+        (1, ((map_y + 1) as f64 * tile - origin_y) / dir_y)
+    } else {
+        (0, f64::INFINITY)
+    };
+
+    let (start, perpendicular) = (sight.start, sight.perpendicular);
+    let hit_at = |map_x: usize, map_y: usize, side: WallSide, travelled: f64| {
+        let x = origin_x + dir_x * travelled;
+        let y = origin_y + dir_y * travelled;
+        let along = match side {
+            WallSide::North | WallSide::South => x,
+            WallSide::East | WallSide::West => y,
         };
+        WallHit {
+            x,
+            y,
+            distance: (start + travelled) * perpendicular,
+            map_x,
+            map_y,
+            side,
+            tex_offset: (along / tile).rem_euclid(1.0),
+            door: false,
+        }
+    };
+
+    // Sorts a wall the ray met into the hit, the layers in front of it or the walls behind it.
+    // Returns the stop if the ray ends there.
+    let mut meet = |wall: WallHit, see_through: bool, travelled: f64, hit: &mut Option<WallHit>, sight: &mut Sight| {
+        let top = sight.top(map, &wall);
+        let shows = hit.is_none() || top < sight.covered;
+        if hit.is_none() && see_through {
+            layers.push(wall);
+        } else if hit.is_none() {
+            *hit = Some(wall);
+        } else if shows {
+            behind.push(wall);
+        }
+        let stop = |through| Stop { hit: *hit, travelled, distance: wall.distance, through };
+        // Mirrors and portals always end this part of the ray, it carries on from their far side.
+        // They're windows rather than walls, so they don't cover what's beyond.
+        if map.redirects_rays(&wall) {
+            return Some(stop(shows.then_some(wall)));
+        }
+        if !see_through {
+            sight.covered = sight.covered.min(top);
+        }
+        sight.hides_beyond(map, wall.distance).then(|| stop(None))
+    };
+
+    let mut hit = None;
 
-        // Choose the closest hit
-        if horz_distance < vert_distance {
-            self.wall_hit_x = horizontal_hit.0;
-            self.wall_hit_y = horizontal_hit.1;
-            self.distance = horz_distance;
-            self.color = 160;  // Darker color for horizontal hits
+    let mut side;
+    let mut travelled;
+    loop {
+        if side_dist_x < side_dist_y {
+            travelled = side_dist_x;
+            side_dist_x += delta_x;
+            map_x += step_x;
+            side = if step_x > 0 { WallSide::West } else { WallSide::East };
         } else {
-            self.wall_hit_x = vertical_hit.0;
-            self.wall_hit_y = vertical_hit.1;
-            self.distance = vert_distance;
-            self.color = 255;  // Brighter color for vertical hits
+            travelled = side_dist_y;
+            side_dist_y += delta_y;
+            map_y += step_y;
+            side = if step_y > 0 { WallSide::North } else { WallSide::South };
         }
 
-        // Correct fish-eye effect
-        let angle_diff = player.rotation_angle - self.ray_angle;
-        self.distance *= angle_diff.cos();
+        if !map.in_bounds(map_x, map_y) {
+            return Stop { hit, travelled, distance: f64::MAX, through: None };
+        }
+        let (col, row) = (map_x as usize, map_y as usize);
 
-        // Adjust color based on distance
-        let mut color_val = (self.color as f64 * (60.0 / self.distance)) as u8;
-        if color_val > 255 { color_val = 255; }
-        self.color = color_val;
+        if let Some(door) = map.door_at(col, row) {
+            // The panel is inset to the middle of the cell. It's only hit if the ray crosses
+            // that line before leaving the cell, and crosses it where the door is still closed.
+            let exit = side_dist_x.min(side_dist_y);
+            let (panel, origin, dir) = if door.along_x {
+                ((map_y as f64 + 0.5) * tile, origin_y, dir_y)
+            } else {
+                ((map_x as f64 + 0.5) * tile, origin_x, dir_x)
+            };
+            if dir != 0.0 {
+                let t = (panel - origin) / dir;
+                if (travelled..=exit).contains(&t) {
+                    let along = if door.along_x { origin_x + dir_x * t } else { origin_y + dir_y * t };
+                    let u = (along / tile).rem_euclid(1.0);
+                    if door.blocks_at(u) {
+                        let side = match (door.along_x, dir > 0.0) {
+                            (true, true) => WallSide::North,
+                            (true, false) => WallSide::South,
+                            (false, true) => WallSide::West,
+                            (false, false) => WallSide::East,
+                        };
+                        let mut wall = hit_at(col, row, side, t);
+                        // Slide the texture along with the panel
+                        wall.tex_offset = u - door.openness;
+                        wall.door = true;
+                        if let Some(stop) = meet(wall, false, t, &mut hit, sight) {
+                            return stop;
+                        }
+                    }
+                }
+            }
+            continue;
+        }
+
+        if map.is_solid(col, row) {
+            let wall = hit_at(col, row, side, travelled);
+            if let Some(stop) = meet(wall, map.is_see_through(col, row), travelled, &mut hit, sight) {
+                return stop;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Player standing in the middle of cell (col, row) looking along `angle`
+    fn player_at(col: f64, row: f64, angle: f64) -> Player {
+        let tile = TILE_SIZE as f64;
+        let mut player = Player::new();
+        player.x = (col + 0.5) * tile;
+        player.y = (row + 0.5) * tile;
+        player.rotation_angle = angle;
+        player
     }
 
-    pub fn render(&self, canvas: &mut Canvas<Window>, player: &Player) {
-        canvas.set_draw_color((255, 0, 0));
-        canvas.draw_line(
-            ((player.x * MINI_MAP_SCALE_FACTOR) as i32, (player.y*MINI_MAP_SCALE_FACTOR) as i32),
-            ((self.wall_hit_x * MINI_MAP_SCALE_FACTOR) as i32, (self.wall_hit_y*MINI_MAP_SCALE_FACTOR) as i32)
-        ).unwrap();
+    #[test]
+    fn mirror_reflects_on_a_map_with_tall_walls() {
+        let map = Map::new();
+        assert!(map.max_wall_height() > 1.0);
+        // The mirror is the east wall of the start room
+        let player = player_at(18.0, 12.0, 0.0);
+        let mut ray = Ray::new(player.rotation_angle);
+        ray.cast(&player, &map);
+
+        let hit = ray.hit.expect("the ray should hit the mirror");
+        assert_eq!((hit.map_x, hit.map_y, hit.side), (23, 12, WallSide::West));
+        assert!(map.redirects_rays(&hit));
+        assert!(ray.layers.is_empty());
+        let reflection = ray.segments.first().expect("the mirror should reflect the ray");
+        assert!(reflection.transform.is_flipped());
+        assert!(reflection.hit.is_some_and(|hit| hit.distance > ray.distance));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn portal_carries_the_ray_on_from_the_linked_face() {
+        let map = Map::new();
+        // The portal in the bottom wall leads out of the west wall at (0, 7), facing east
+        let player = player_at(10.0, 17.0, PI / 2.0);
+        let mut ray = Ray::new(player.rotation_angle);
+        ray.cast(&player, &map);
+
+        let hit = ray.hit.expect("the ray should hit the portal");
+        assert_eq!((hit.map_x, hit.map_y, hit.side), (10, 19, WallSide::North));
+        let beyond = ray.segments.first().expect("the portal should carry the ray on");
+        let tile = TILE_SIZE as f64;
+        assert!((beyond.start_x - tile).abs() < 1e-6);
+        assert_eq!((beyond.start_y / tile).floor(), 7.0);
+        // Going east, away from the exit face
+        assert!(beyond.end_x > beyond.start_x);
+        assert!(!beyond.transform.is_flipped());
+    }
+
+    #[test]
+    fn hit_is_the_nearest_wall_that_blocks_sight() {
+        let map = Map::new();
+        let player = player_at(18.0, 12.0, PI);
+        let mut ray = Ray::new(player.rotation_angle);
+        ray.cast(&player, &map);
+
+        let hit = ray.hit.expect("the ray should hit the red room");
+        assert_eq!((hit.map_x, hit.map_y, hit.side), (8, 12, WallSide::East));
+        // Everything behind is one story tall and lower on screen than the red wall
+        assert!(ray.layers.is_empty());
+        assert!(ray.behind.is_empty());
+    }
+
+    #[test]
+    fn walls_show_over_low_walls_until_the_tallest_is_covered() {
+        let map = Map::new();
+        // Looking west along row 2, over the waist-high green wall towards the two story tower
+        let player = player_at(19.0, 2.0, PI);
+        let mut ray = Ray::new(player.rotation_angle);
+        ray.cast(&player, &map);
+
+        let hit = ray.hit.expect("the ray should hit the green wall");
+        assert_eq!((hit.map_x, hit.map_y), (17, 2));
+        let behind: Vec<_> = ray.behind.iter().map(|wall| (wall.map_x, wall.map_y)).collect();
+        // The rest of the green wall is hidden behind its own front, the grey wall and the tower show
+        assert_eq!(behind, [(9, 2), (3, 2)]);
+        // Nothing can show over the tower, so the ray stops there
+        assert_eq!(ray.distance, ray.behind[1].distance);
+        assert!(ray.wall_hit_x > 3.0 * TILE_SIZE as f64);
+    }
+}
@@ -1,116 +1,384 @@
 use crate::globals::*;
 use crate::map::Map;
+use crate::portal::Face;
 use crate::player::Player;
-use crate::ray::Ray;
+use crate::ray::{Ray, RaySegment, WallHit};
+use crate::sky::Sky;
 use crate::sprite::Sprite;
-use crate::texture::TextureManager;
-use sdl3::pixels::Color;
-use sdl3::rect::Rect;
-use sdl3::render::Canvas;
-use sdl3::video::Window;
+use crate::texture::{PixelTexture, TextureManager};
+use crate::framebuffer::{FrameBuffer, TextureSlice};
+use crate::lighting::{Falloff, Lighting};
+use rayon::prelude::*;
 
 pub struct Raycaster {
     pub rays: Vec<Ray>,
+    // Perpendicular distance to where the ray stopped in each screen column, filled by the wall
+    // pass and used to clip sprites column by column. Sprites nearer than that but beyond a low
+    // wall can still show over its top.
+    pub depth_buffer: Vec<f64>,
+    pub lighting: Lighting,
+    // Horizontal field of view in radians, can be changed between frames
+    pub fov: f64,
+    // Game time in seconds, which animated wall textures and drifting sky layers run on
+    pub time: f64,
+    pub sky: Sky,
+}
+
+// Everything a frame is drawn from, bundled up for the passes that need all of it
+struct Scene<'a> {
+    texture_manager: &'a TextureManager,
+    map: &'a Map,
+    player: &'a Player,
+    sprites: &'a [Sprite],
+    // Flat textures by id, looked up once per frame
+    flats: [Option<&'a PixelTexture>; 256],
+}
+
+// Where a sprite lands on screen
+struct SpriteProjection {
+    left: i32,
+    top: i32,
+    size: i32,
+    // Perpendicular distance, for depth tests, and straight-line distance, for lighting
+    depth: f64,
+    distance: f64,
+}
+
+// Columns cast in one go by a worker thread. Small enough to keep every thread busy when some
+// parts of the view take longer to cast than others.
+const COLUMNS_PER_TASK: usize = 32;
+
+// Closest anything is drawn from, in world units
+const NEAREST: f64 = 0.5;
+
+// Limits for the adjustable field of view, in degrees
+pub const MIN_FOV_DEGREES: f64 = 30.0;
+pub const MAX_FOV_DEGREES: f64 = 120.0;
+
+impl Default for Raycaster {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Raycaster {
     pub fn new() -> Self {
         Raycaster {
             rays: Vec::new(),
+            depth_buffer: Vec::new(),
+            lighting: Lighting::new(Falloff::Linear, FOG_COLOR),
+            fov: FOV,
+            time: 0.0,
+            sky: Sky::new(),
         }
     }
 
-    pub fn cast_all_rays(&mut self, player: &Player, map: &Map) {
-        self.rays.clear();
-        
-        let ray_angle_step = FOV / NUM_RAYS as f64;
-        let mut ray_angle = player.rotation_angle - FOV / 2.0;
-        
-        for i in 0..NUM_RAYS {
-            let mut ray = Ray::new(ray_angle);
-            ray.cast(player, map);
-            self.rays.push(ray);
-            ray_angle += ray_angle_step;
-            // ray.render(canvas, player); // Remove this line if not needed, or move rendering to after collecting rays
+    // Casts one ray per column of a frame `columns` pixels wide. Rays are independent of each
+    // other, so strips of columns are cast on rayon's worker threads. The workers stay up between
+    // frames, as starting eight threads every frame took longer than each one's share of the
+    // rays. The ray buffer is kept between frames and every ray's angle comes from its column
+    // index alone, so the result is the same however many threads do the work.
+    pub fn cast_all_rays(&mut self, player: &Player, map: &Map, columns: u32) {
+        let num_rays = columns as usize;
+        if self.rays.len() != num_rays {
+            self.rays = (0..num_rays).map(|_| Ray::new(0.0)).collect();
         }
+
+        let fov = self.fov;
+        self.rays.par_chunks_mut(COLUMNS_PER_TASK).enumerate().for_each(|(chunk_index, chunk)| {
+            cast_rays(chunk, chunk_index * COLUMNS_PER_TASK, num_rays, fov, player, map);
+        });
     }
 
-    pub fn render_all_rays(&self, canvas: &mut Canvas<Window>,player: &Player) {
+    pub fn render_all_rays(&self, frame: &mut FrameBuffer, player: &Player) {
         for ray in &self.rays {
-            ray.render(canvas, player);
+            ray.render(frame, player);
+        }
+    }
+
+    // Draws everything the game shows in one frame, in the same order the window does.
+    // Used both for the SDL window and for headless rendering.
+    pub fn render_frame(&mut self, frame: &mut FrameBuffer, texture_manager: &TextureManager, map: &Map, player: &Player, sprites: &[Sprite]) {
+        frame.clear((0, 0, 0));
+        self.render(frame, texture_manager, map, player, sprites);
+        map.render(frame);
+        self.render_sprites(frame, texture_manager, map, player, sprites);
+        player.render(frame);
+        self.render_all_rays(frame, player);
+        self.render_weapon(frame, texture_manager);
+    }
+
+    pub fn render(&mut self, frame: &mut FrameBuffer, texture_manager: &TextureManager, map: &Map, player: &Player, sprites: &[Sprite]) {
+        let horizon = horizon(frame, player);
+        let scene = Scene {
+            texture_manager,
+            map,
+            player,
+            sprites,
+            flats: std::array::from_fn(|id| texture_manager.get_wall_texture(id as u8, self.time)),
+        };
+
+        self.sky.render(frame, texture_manager, &self.rays, player.rotation_angle, horizon, self.time);
+        self.render_floor_and_ceiling(frame, &scene);
+
+        self.depth_buffer.clear();
+        self.depth_buffer.resize(frame.width as usize, f64::MAX);
+
+        for (i, ray) in self.rays.iter().enumerate() {
+            if let Some(depth) = self.depth_buffer.get_mut(i) {
+                *depth = ray.distance;
+            }
+            // Furthest first: walls showing over the nearest opaque one, that wall, then the
+            // see-through walls in front of it
+            for wall in ray.back_to_front() {
+                if map.redirects_rays(wall) {
+                    self.draw_through(frame, &scene, i as i32, ray, 0, wall);
+                } else {
+                    self.draw_wall_slice(frame, texture_manager, map, player, i as i32, wall);
+                }
+            }
         }
     }
 
-    pub fn render(&self, canvas: &mut Canvas<Window>, texture_manager: &mut TextureManager, map: &Map, player: &Player) {
-        // Render sky
-        let half_height_u32 = HALF_HEIGHT as u32;
+    // Screen rows a wall covers, as (top, height). The wall's foot is eye height below the
+    // horizon and its top depends on how many stories tall it is.
+    fn wall_span(&self, frame: &FrameBuffer, map: &Map, player: &Player, hit: &WallHit) -> (i32, i32) {
+        let stories = map.get_wall_height(hit.map_x, hit.map_y);
+        let scale = self.scale_at(frame, hit.distance);
+        let wall_bottom = horizon(frame, player).saturating_add((player.eye_height * scale) as i32);
+        let wall_height = (stories * TILE_SIZE as f64 * scale) as i32;
+        (wall_bottom.saturating_sub(wall_height), wall_height)
+    }
+
+    fn draw_wall_slice(&self, frame: &mut FrameBuffer, texture_manager: &TextureManager, map: &Map, player: &Player, column: i32, hit: &WallHit) {
+        let stories = map.get_wall_height(hit.map_x, hit.map_y);
+        let (wall_top, wall_height) = self.wall_span(frame, map, player, hit);
         
-        // Render sky with texture
-        if let Some(sky_texture) = texture_manager.get_texture_mut("sky") {
-            // Calculate sky offset based on player rotation
-            let sky_width = sky_texture.query().width;
-            let offset_x = (player.rotation_angle / (2.0 * std::f64::consts::PI) * sky_width as f64) as i32;
-            
-            // Draw sky in two parts to handle wrapping
-            let part1_width = sky_width - offset_x as u32;
-            let part2_width = WINDOW_WIDTH - part1_width;
+        let texture_id = map.get_face_texture_id(hit.map_x, hit.map_y, hit.side);
+        
+        let (front_x, front_y) = hit.front_cell();
+        let light = self.lighting.light_at(hit.distance, map.get_light_level(front_x, front_y), hit.x, hit.y);
+        
+        if let Some(texture) = texture_manager.get_wall_texture(texture_id, self.time) {
+            let tex_x = ((hit.texture_u() * texture.width as f64) as u32).min(texture.width - 1);
             
-            // First part of sky
-            let src_rect1 = Rect::new(offset_x, 0, part1_width, sky_texture.query().height);
-            let dst_rect1 = Rect::new(0, 0, part1_width, half_height_u32);
-            canvas.copy(sky_texture, src_rect1, dst_rect1).unwrap();
+            let ramp = self.lighting.ramp(light);
             
-            // Second part of sky (wrapped around)
-            if part2_width > 0 {
-                let src_rect2 = Rect::new(0, 0, part2_width, sky_texture.query().height);
-                let dst_rect2 = Rect::new(part1_width as i32, 0, part2_width, half_height_u32);
-                canvas.copy(sky_texture, src_rect2, dst_rect2).unwrap();
-            }
+            frame.draw_wall_column(texture, column, &TextureSlice { tex_x, top: wall_top, height: wall_height, repeat: stories, ramp });
         } else {
-            canvas.set_draw_color(SKY_COLOR);
-            canvas.fill_rect(Rect::new(0, 0, WINDOW_WIDTH, half_height_u32)).unwrap();
+            // Fallback rendering
+            let [r, g, b, _] = self.lighting.ramp(light).apply([100, 100, 100, 255]);
+            frame.fill_rect(column, wall_top, 1, wall_height as u32, (r, g, b));
         }
-        canvas.set_draw_color(FLOOR_COLOR);
-        canvas.fill_rect(Rect::new(0, HALF_HEIGHT, WINDOW_WIDTH, HALF_HEIGHT.try_into().unwrap())).unwrap();
 
-        for (i, ray) in self.rays.iter().enumerate() {
-            if ray.distance < MAX_DEPTH {
-                let wall_height = (TILE_SIZE as f64 / ray.distance * 300.0) as i32;
-                let wall_top = HALF_HEIGHT - wall_height / 2;
-                
-                let texture_id = map.get_wall_texture_id(
-                    (ray.wall_hit_x / TILE_SIZE as f64) as usize,
-                    (ray.wall_hit_y / TILE_SIZE as f64) as usize
-                );
-                
-                let texture_name = format!("wall_{}", texture_id);
-                
-                if let Some(texture) = texture_manager.get_texture_mut(&texture_name) {
-                    let wall_x = ray.wall_hit_x % TILE_SIZE as f64;
-                    let tex_x = (wall_x / TILE_SIZE as f64 * 64.0) as i32;
-                    
-                    let shade_factor = (1.0 - (ray.distance / MAX_DEPTH).min(1.0)) * 0.8 + 0.2; //I don't even remember what this is for. todo reference pikuma
-                    let shade_value = (255.0 * shade_factor) as u8;
-                    
-                    texture.set_color_mod(shade_value, shade_value, shade_value);
-                    
-                    let src_rect = Rect::new(tex_x, 0, TEX_X_DIM,TEX_Y_DIM);
-                    let dst_rect = Rect::new(i as i32, wall_top, 1, wall_height as u32);
-                    
-                    canvas.copy(texture, src_rect, dst_rect).unwrap();
-                    texture.set_color_mod(255, 255, 255);
-                } else {
-                    // Fallback rendering
-                    let shade_factor = (1.0 - (ray.distance / MAX_DEPTH).min(1.0)) * 0.8 + 0.2;
-                    let color_value = (100.0 * shade_factor) as u8;
-                    canvas.set_draw_color(Color::RGB(color_value, color_value, color_value));
-                    canvas.fill_rect(Rect::new(i as i32, wall_top, 1, wall_height as u32)).unwrap();
+        // Door panels slide, so only fixed faces carry decals
+        if hit.door {
+            return;
+        }
+        let scale = self.scale_at(frame, hit.distance);
+        let wall_bottom = wall_top.saturating_add(wall_height);
+        let u = hit.texture_u();
+        for decal in map.decals.on_face(&Face::new(hit.map_x, hit.map_y, hit.side)) {
+            let half_width = decal.size / 2.0 / TILE_SIZE as f64;
+            let across = (u - (decal.u - half_width)) / (2.0 * half_width);
+            if !(0.0..1.0).contains(&across) {
+                continue;
+            }
+            if let Some(texture) = texture_manager.get_texture(&decal.texture_name) {
+                let tex_x = (across * texture.width as f64) as u32;
+                let top = wall_bottom - ((decal.height + decal.size / 2.0) * scale) as i32;
+                let height = (decal.size * scale) as i32;
+                frame.draw_texture_column(texture, tex_x, column, top, height, self.lighting.ramp(light));
+            }
+        }
+    }
+
+    // Draws a mirror or portal in `column`: the rest of the ray beyond it (segment number
+    // `segment`), clipped to the face's rows. Mirrors tint what they show. Once the ray has run
+    // out of segments, a mirror is just a tinted surface and a portal shows fog.
+    fn draw_through(&self, frame: &mut FrameBuffer, scene: &Scene, column: i32, ray: &Ray, segment: usize, face: &WallHit) {
+        let map = scene.map;
+        let (top, height) = self.wall_span(frame, map, scene.player, face);
+        let tint = if map.portal_at(face).is_some() { None } else { map.mirror_tint(face.map_x, face.map_y) };
+        let Some(beyond) = ray.segments.get(segment) else {
+            let (front_x, front_y) = face.front_cell();
+            let light = self.lighting.light_at(face.distance, map.get_light_level(front_x, front_y), face.x, face.y);
+            let color = match tint {
+                Some((r, g, b)) => {
+                    let [r, g, b, _] = self.lighting.ramp(light).apply([r, g, b, 255]);
+                    (r, g, b)
+                }
+                None => self.lighting.fog_color,
+            };
+            frame.fill_rect(column, top, 1, height.max(0) as u32, color);
+            return;
+        };
+
+        // Keep what's beyond inside the face, and inside any mirror or portal this one is seen through
+        let outer_clip = frame.clip_rect();
+        let clip_top = top.max(outer_clip.1);
+        let clip_bottom = top.saturating_add(height).min(outer_clip.1 + outer_clip.3 as i32);
+        if clip_bottom <= clip_top {
+            return;
+        }
+        frame.set_clip_rect(Some((column, clip_top, 1, (clip_bottom - clip_top) as u32)));
+
+        self.draw_flats_beyond(frame, scene, column, ray, beyond);
+        self.draw_walls_and_sprites_beyond(frame, scene, column, ray, segment, face.distance);
+
+        frame.set_clip_rect(Some(outer_clip));
+        if let Some(tint) = tint {
+            frame.tint_rect(column, top, 1, height.max(0) as u32, tint);
+        }
+    }
+
+    // Floor and ceiling seen through a mirror or portal. To the viewer the floor carries straight
+    // on, so each row's distance is the same as without them; only the real spot it shows is different.
+    fn draw_flats_beyond(&self, frame: &mut FrameBuffer, scene: &Scene, column: i32, ray: &Ray, beyond: &RaySegment) {
+        let player = scene.player;
+        let correction = 1.0 / (player.rotation_angle - ray.ray_angle).cos();
+        let (dir_x, dir_y) = (ray.ray_angle.cos() * correction, ray.ray_angle.sin() * correction);
+        let to_real = beyond.transform.inverse();
+        let (_, clip_y, _, clip_height) = frame.clip_rect();
+        for y in clip_y..clip_y + clip_height as i32 {
+            let Some((row_distance, is_floor)) = self.row_distance(frame, player, y) else {
+                continue;
+            };
+            let (world_x, world_y) = to_real.apply(player.x + dir_x * row_distance, player.y + dir_y * row_distance);
+            if let Some(color) = self.flat_color(scene, world_x, world_y, row_distance, is_floor) {
+                frame.put_pixel(column, y, color);
+            }
+        }
+    }
+
+    // Walls and sprites seen through a mirror or portal, interleaved and drawn back to front. A
+    // sprite shows up where the mirrors and portals make it appear; only images beyond the face
+    // and in front of where the ray stopped there are visible.
+    fn draw_walls_and_sprites_beyond(&self, frame: &mut FrameBuffer, scene: &Scene, column: i32, ray: &Ray, segment: usize, face_distance: f64) {
+        let player = scene.player;
+        let beyond = &ray.segments[segment];
+        let far = beyond.distance;
+        // The sprites face where the viewer appears to be from their side
+        let (viewer_x, viewer_y) = beyond.transform.inverse().apply(player.x, player.y);
+
+        let mut images: Vec<(&Sprite, &PixelTexture, SpriteProjection)> = scene.sprites.iter()
+            .filter(|sprite| sprite.visible)
+            .filter_map(|sprite| {
+                let view_angle = sprite.view_angle(viewer_x, viewer_y);
+                let texture = scene.texture_manager.get_sprite_texture(&sprite.texture_name, view_angle)?;
+                let (x, y) = beyond.transform.apply(sprite.x, sprite.y);
+                let projection = self.project_sprite(frame, player, x, y, sprite.z)?;
+                let covers_column = (projection.left..projection.left + projection.size).contains(&column);
+                (covers_column && projection.depth > face_distance && projection.depth < far)
+                    .then_some((sprite, texture, projection))
+            })
+            .collect();
+        images.sort_by(|a, b| b.2.depth.partial_cmp(&a.2.depth).unwrap());
+
+        let mut images = images.into_iter().peekable();
+        for wall in beyond.back_to_front() {
+            while let Some(image) = images.next_if(|image| image.2.depth > wall.distance) {
+                self.draw_sprite_image_column(frame, scene, column, beyond, &image);
+            }
+            if scene.map.redirects_rays(wall) {
+                self.draw_through(frame, scene, column, ray, segment + 1, wall);
+            } else {
+                self.draw_wall_slice(frame, scene.texture_manager, scene.map, player, column, wall);
+            }
+        }
+        for image in images {
+            self.draw_sprite_image_column(frame, scene, column, beyond, &image);
+        }
+    }
+
+    fn draw_sprite_image_column(&self, frame: &mut FrameBuffer, scene: &Scene, column: i32, beyond: &RaySegment, image: &(&Sprite, &PixelTexture, SpriteProjection)) {
+        let (sprite, texture, projection) = image;
+        let tile = TILE_SIZE as f64;
+        let cell_light = scene.map.get_light_level((sprite.x / tile) as usize, (sprite.y / tile) as usize);
+        let ramp = self.lighting.ramp(self.lighting.light_at(projection.distance, cell_light, sprite.x, sprite.y));
+        let mut tex_x = ((column - projection.left) as u64 * texture.width as u64 / projection.size as u64) as u32;
+        // Mirrors swap left and right
+        if beyond.transform.is_flipped() {
+            tex_x = texture.width - 1 - tex_x;
+        }
+        frame.draw_texture_column(texture, tex_x, column, projection.top, projection.size, ramp);
+    }
+
+    // Floor and ceiling casting. Every screen row below (or above) the horizon sees the floor (or
+    // ceiling) at one fixed distance, so for each row we only need to find which world point each
+    // column's ray lands on at that distance and sample that cell's flat there.
+    fn render_floor_and_ceiling(&self, frame: &mut FrameBuffer, scene: &Scene) {
+        let player = scene.player;
+
+        // Rays are spread by angle, so undo the fish-eye correction to get from the
+        // perpendicular row distance to the distance along each ray
+        let directions: Vec<(f64, f64)> = self.rays.iter()
+            .map(|ray| {
+                let correction = 1.0 / (player.rotation_angle - ray.ray_angle).cos();
+                (ray.ray_angle.cos() * correction, ray.ray_angle.sin() * correction)
+            })
+            .collect();
+
+        for y in 0..frame.height as i32 {
+            let Some((row_distance, is_floor)) = self.row_distance(frame, player, y) else {
+                continue;
+            };
+
+            for (x, (dir_x, dir_y)) in directions.iter().enumerate() {
+                let world_x = player.x + dir_x * row_distance;
+                let world_y = player.y + dir_y * row_distance;
+                if let Some(color) = self.flat_color(scene, world_x, world_y, row_distance, is_floor) {
+                    frame.put_pixel(x as i32, y, color);
                 }
             }
         }
     }
 
-    pub fn render_sprites(&self, canvas: &mut Canvas<Window>, texture_manager: &mut TextureManager, player: &Player, sprites: &[Sprite]) {
+    // Perpendicular distance to the floor (or ceiling) seen on screen row `y`, and whether it's
+    // the floor. None on the horizon, or if the eye is outside the story it looks at.
+    fn row_distance(&self, frame: &FrameBuffer, player: &Player, y: i32) -> Option<(f64, bool)> {
+        let horizon = horizon(frame, player);
+        if y == horizon {
+            return None;
+        }
+        // Height of the eye above the floor and below the ceiling (one story up), projected
+        // with the same scale the walls use
+        let is_floor = y > horizon;
+        let eye_offset = if is_floor { player.eye_height } else { TILE_SIZE as f64 - player.eye_height };
+        let row_distance = eye_offset * self.projection(frame) / (y - horizon).abs() as f64;
+        // Jumped above the ceiling or sunk into the floor, nothing sensible to draw
+        (row_distance > 0.0).then_some((row_distance, is_floor))
+    }
+
+    // Lit colour of the floor or ceiling at a world position. None where an open ceiling should
+    // keep the sky that is already there.
+    fn flat_color(&self, scene: &Scene, world_x: f64, world_y: f64, distance: f64, is_floor: bool) -> Option<[u8; 4]> {
+        let map = scene.map;
+        let tile = TILE_SIZE as f64;
+        let col = (world_x / tile).floor();
+        let row = (world_y / tile).floor();
+
+        let (texture_id, cell_light) = if col < 0.0 || row < 0.0 {
+            (0, 255)
+        } else if is_floor {
+            (map.get_floor_texture_id(col as usize, row as usize), map.get_light_level(col as usize, row as usize))
+        } else {
+            (map.get_ceiling_texture_id(col as usize, row as usize), map.get_light_level(col as usize, row as usize))
+        };
+        let ramp = self.lighting.ramp(self.lighting.light_at(distance, cell_light, world_x, world_y));
+
+        match scene.flats[texture_id as usize] {
+            Some(texture) if texture_id > 0 => {
+                let tex_x = ((world_x / tile - col) * texture.width as f64) as u32;
+                let tex_y = ((world_y / tile - row) * texture.height as f64) as u32;
+                Some(ramp.apply(texture.sample(tex_x, tex_y)))
+            }
+            _ if is_floor => Some(ramp.apply([FLOOR_COLOR.0, FLOOR_COLOR.1, FLOOR_COLOR.2, 255])),
+            _ => None,
+        }
+    }
+
+    pub fn render_sprites(&self, frame: &mut FrameBuffer, texture_manager: &TextureManager, map: &Map, player: &Player, sprites: &[Sprite]) {
         let mut sprite_distances: Vec<(usize, f64)> = sprites.iter()
             .enumerate()
             .map(|(i, sprite)| {
@@ -122,48 +390,235 @@ impl Raycaster {
         
         sprite_distances.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
         
-        for (sprite_index, distance) in sprite_distances {
+        for (sprite_index, _) in sprite_distances {
             let sprite = &sprites[sprite_index];
-            if !sprite.visible || distance > MAX_DEPTH { continue; }
-            
-            let dx = sprite.x - player.x;
-            let dy = sprite.y - player.y;
-            let mut angle_to_sprite = dy.atan2(dx) - player.rotation_angle;
-            
-            // Normalize angle
-            angle_to_sprite = angle_to_sprite.rem_euclid(2.0 * std::f64::consts::PI);
-            if angle_to_sprite > std::f64::consts::PI {
-                angle_to_sprite -= 2.0 * std::f64::consts::PI;
-            }
-            
-            if angle_to_sprite.abs() > FOV / 2.0 { continue; }
-            
-            let screen_x = ((WINDOW_WIDTH as f64 / 2.0) 
-                + (angle_to_sprite / FOV * WINDOW_WIDTH as f64)) as i32;
-                
-            let sprite_size = (TILE_SIZE as f64 / distance * 300.0) as i32;
-            let sprite_screen_y = HALF_HEIGHT - sprite_size / 2;
-            
-            let ray_index = (screen_x as f64 / WINDOW_WIDTH as f64 * NUM_RAYS as f64) as usize;
-            if ray_index < self.rays.len() && self.rays[ray_index].distance > distance {
-                if let Some(texture) = texture_manager.get_texture_mut(&sprite.texture_name) {
-                    let shade_factor = (1.0 - (distance / MAX_DEPTH).min(1.0)) * 0.8 + 0.2;
-                    let shade_value = (255.0 * shade_factor) as u8;
-                    texture.set_color_mod(shade_value, shade_value, shade_value);
-                    
-                    canvas.copy(
-                        texture,
-                        None,
-                        Rect::new(
-                            screen_x - sprite_size / 2,
-                            sprite_screen_y,
-                            sprite_size as u32,
-                            sprite_size as u32
-                        )
-                    ).unwrap();
-                    texture.set_color_mod(255, 255, 255);
+            if !sprite.visible { continue; }
+
+            let view_angle = sprite.view_angle(player.x, player.y);
+            let Some(texture) = texture_manager.get_sprite_texture(&sprite.texture_name, view_angle) else {
+                continue;
+            };
+            let Some(projection) = self.project_sprite(frame, player, sprite.x, sprite.y, sprite.z) else {
+                continue;
+            };
+
+            let tile = TILE_SIZE as f64;
+            let cell_light = map.get_light_level((sprite.x / tile) as usize, (sprite.y / tile) as usize);
+            let ramp = self.lighting.ramp(self.lighting.light_at(projection.distance, cell_light, sprite.x, sprite.y));
+
+            let x_start = projection.left.max(0);
+            let x_end = (projection.left + projection.size).min(frame.width as i32);
+            for x in x_start..x_end {
+                if self.depth_buffer.get(x as usize).is_some_and(|&depth| depth < projection.depth) {
+                    continue;
+                }
+                let tex_x = ((x - projection.left) as u64 * texture.width as u64 / projection.size as u64) as u32;
+                frame.draw_texture_column(texture, tex_x, x, projection.top, projection.size, ramp);
+
+                // Walls in front of the sprite cover it again: see-through walls, and low walls
+                // it's only showing over
+                if let Some(ray) = self.rays.get(x as usize) {
+                    for wall in ray.back_to_front().filter(|wall| wall.distance < projection.depth) {
+                        self.draw_wall_slice(frame, texture_manager, map, player, x, wall);
+                    }
                 }
             }
         }
     }
-}
\ No newline at end of file
+
+    // Projects a sprite standing at (x, y) with its feet `z` above the floor. Sprites are a tile
+    // tall and as wide. None if it's out of view.
+    fn project_sprite(&self, frame: &FrameBuffer, player: &Player, x: f64, y: f64, z: f64) -> Option<SpriteProjection> {
+        let dx = x - player.x;
+        let dy = y - player.y;
+        let distance = (dx * dx + dy * dy).sqrt();
+
+        let mut angle_to_sprite = dy.atan2(dx) - player.rotation_angle;
+
+        // Normalize angle
+        angle_to_sprite = angle_to_sprite.rem_euclid(2.0 * std::f64::consts::PI);
+        if angle_to_sprite > std::f64::consts::PI {
+            angle_to_sprite -= 2.0 * std::f64::consts::PI;
+        }
+
+        if angle_to_sprite.abs() > self.fov / 2.0 {
+            return None;
+        }
+
+        // Sprites are projected and compared against the depth buffer by perpendicular
+        // distance, like the walls
+        let depth = distance * angle_to_sprite.cos();
+        // Right on top of the viewer there's no sensible size to draw it at
+        if depth < NEAREST {
+            return None;
+        }
+        let projection = self.projection(frame);
+        let screen_x = ((frame.width as f64 / 2.0) + angle_to_sprite.tan() * projection) as i32;
+
+        let scale = projection / depth;
+        let size = (TILE_SIZE as f64 * scale) as i32;
+        let bottom = horizon(frame, player).saturating_add(((player.eye_height - z) * scale) as i32);
+
+        Some(SpriteProjection { left: screen_x - size / 2, top: bottom - size, size, depth, distance })
+    }
+
+    // How high up a wall `distance` away the middle of the screen is, i.e. where a shot fired
+    // straight ahead lands. Looking up or down moves it up or down the wall.
+    pub fn aim_height(&self, frame: &FrameBuffer, player: &Player, distance: f64) -> f64 {
+        let rows_below_horizon = frame.height as i32 / 2 - horizon(frame, player);
+        player.eye_height - rows_below_horizon as f64 * distance / self.projection(frame)
+    }
+
+    fn projection(&self, frame: &FrameBuffer) -> f64 {
+        projection_distance(frame.width as f64, self.fov)
+    }
+
+    // Pixels per world unit at `distance`. Walls nearer than NEAREST, which the viewer can be
+    // flush against, are drawn as if they were that far away.
+    fn scale_at(&self, frame: &FrameBuffer, distance: f64) -> f64 {
+        self.projection(frame) / distance.max(NEAREST)
+    }
+
+    pub fn render_weapon(&self, frame: &mut FrameBuffer, texture_manager: &TextureManager) {
+        if let Some(weapon_texture) = texture_manager.get_texture("weapon") {
+            // A quarter of the frame's height, whatever the resolution, unless the frame is
+            // narrower than that
+            let size = (frame.height / 4).min(frame.width);
+            frame.blit(
+                weapon_texture,
+                (frame.width - size) as i32 / 2,
+                (frame.height - size) as i32,
+                size,
+                size,
+                self.lighting.full_bright()
+            );
+        }
+    }
+}
+
+// Casts a run of rays starting at screen column `first_column`. Rays go through the middle of
+// their column on the projection plane, so they're evenly spaced across the screen rather than
+// in angle, which would squash the edges of the view.
+fn cast_rays(rays: &mut [Ray], first_column: usize, columns: usize, fov: f64, player: &Player, map: &Map) {
+    let plane_distance = projection_distance(columns as f64, fov);
+    for (i, ray) in rays.iter_mut().enumerate() {
+        let column_offset = (first_column + i) as f64 + 0.5 - columns as f64 / 2.0;
+        ray.set_angle(player.rotation_angle + (column_offset / plane_distance).atan());
+        ray.cast(player, map);
+    }
+}
+
+// Distance from the eye to a projection plane `width` pixels wide that exactly spans the field of
+// view. A wall one unit tall at distance d is this many pixels tall divided by d.
+fn projection_distance(width: f64, fov: f64) -> f64 {
+    width / 2.0 / (fov / 2.0).tan()
+}
+
+// Screen row of the horizon, moved off the middle of the frame by the player's pitch
+fn horizon(frame: &FrameBuffer, player: &Player) -> i32 {
+    frame.height as i32 / 2 + (player.pitch * frame.height as f64 / WINDOW_HEIGHT as f64) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn threaded_casting_matches_one_thread() {
+        let map = Map::new();
+        let tile = TILE_SIZE as f64;
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        let columns = 320;
+        // All round from the start, and across the low green wall towards the tower
+        let (start_x, start_y) = (map.spawn.x, map.spawn.y);
+        let views = [
+            (start_x, start_y, 0.0),
+            (start_x, start_y, PI / 2.0),
+            (start_x, start_y, PI),
+            (start_x, start_y, 1.5 * PI),
+            (19.5 * tile, 2.5 * tile, PI),
+        ];
+
+        let mut raycaster = Raycaster::new();
+        let mut player = Player::new();
+        let (mut segments, mut behind) = (0, 0);
+        for (x, y, angle) in views {
+            (player.x, player.y, player.rotation_angle) = (x, y, angle);
+            pool.install(|| raycaster.cast_all_rays(&player, &map, columns));
+
+            let mut rays: Vec<Ray> = (0..columns).map(|_| Ray::new(0.0)).collect();
+            cast_rays(&mut rays, 0, columns as usize, raycaster.fov, &player, &map);
+            assert_eq!(raycaster.rays, rays);
+            segments += rays.iter().filter(|ray| !ray.segments.is_empty()).count();
+            behind += rays.iter().filter(|ray| !ray.behind.is_empty()).count();
+        }
+        // The views take in mirrors and walls behind low walls, not just plain walls
+        assert!(segments > 0 && behind > 0);
+    }
+
+    fn pixel(frame: &FrameBuffer, x: u32, y: u32) -> (u8, u8, u8) {
+        let i = ((y * frame.width + x) * 4) as usize;
+        (frame.pixels[i], frame.pixels[i + 1], frame.pixels[i + 2])
+    }
+
+    #[test]
+    fn frame_shows_wall_sky_and_minimap() {
+        let map = Map::new();
+        let tile = TILE_SIZE as f64;
+        // Only the outer wall has a texture, so the floor and sky fall back to their plain colours
+        let mut texture_manager = TextureManager::empty();
+        texture_manager.add_wall_texture(1, PixelTexture::solid(8, 8, [200, 40, 40]));
+        let mut player = Player::new();
+        // In the open north-west room, facing the west wall two and a half tiles away
+        (player.x, player.y, player.rotation_angle) = (3.5 * tile, 5.5 * tile, PI);
+        let mut frame = FrameBuffer::new(600, 400);
+        let mut raycaster = Raycaster::new();
+        raycaster.cast_all_rays(&player, &map, frame.width);
+        raycaster.render_frame(&mut frame, &texture_manager, &map, &player, &[]);
+
+        // Just below the horizon in the middle of the view is the wall, its red only darkened
+        let (r, g, b) = pixel(&frame, 300, 210);
+        assert!(r > 100 && g == b && g < r / 3, "wall is {:?}", (r, g, b));
+        // Well above the wall's top, with no sky texture and no ceiling, is the plain sky
+        assert_eq!(pixel(&frame, 500, 5), SKY_COLOR);
+        // The minimap sits in the top left corner, starting with the corner wall
+        assert_eq!(pixel(&frame, 0, 0), (100, 100, 100));
+    }
+
+    #[test]
+    fn viewer_flush_against_a_wall() {
+        // Starting on the edge of the open column, facing the wall right next to it
+        let map = Map::parse("[grid]\n1 1 1 1\n1 1 0 1\n1 1 0 1\n1 1 1 1\n[player]\nstart 2 1.5 180\n").unwrap();
+        let mut player = Player::new();
+        (player.x, player.y, player.rotation_angle) = (map.spawn.x, map.spawn.y, map.spawn.angle);
+        let mut texture_manager = TextureManager::empty();
+        texture_manager.add_wall_texture(1, PixelTexture::solid(8, 8, [200, 40, 40]));
+        texture_manager.add_texture("thing", PixelTexture::solid(8, 8, [0, 200, 0]));
+        // Something standing right where the viewer is
+        let sprites = [Sprite::new(player.x, player.y, "thing")];
+        let mut frame = FrameBuffer::new(320, 200);
+        let mut raycaster = Raycaster::new();
+        raycaster.cast_all_rays(&player, &map, frame.width);
+        raycaster.render_frame(&mut frame, &texture_manager, &map, &player, &sprites);
+
+        let middle = &raycaster.rays[160];
+        assert_eq!(middle.hit.map(|hit| (hit.map_x, hit.map_y)), Some((1, 1)));
+        assert!(middle.distance < 1e-9);
+        // The wall fills the whole column
+        assert_eq!(pixel(&frame, 160, 199), (200, 40, 40));
+        assert_eq!(pixel(&frame, 160, 100), (200, 40, 40));
+    }
+
+    #[test]
+    fn weapon_fits_a_tall_narrow_frame() {
+        let mut texture_manager = TextureManager::empty();
+        texture_manager.add_texture("weapon", PixelTexture::solid(8, 8, [0, 0, 200]));
+        let mut frame = FrameBuffer::new(100, 800);
+        Raycaster::new().render_weapon(&mut frame, &texture_manager);
+        // As wide as the frame, along the bottom
+        assert_eq!(pixel(&frame, 0, 799), (0, 0, 200));
+        assert_eq!(pixel(&frame, 99, 700), (0, 0, 200));
+        assert_eq!(pixel(&frame, 50, 699), (0, 0, 0));
+    }
+}
@@ -0,0 +1,81 @@
+use crate::framebuffer::FrameBuffer;
+use crate::globals::SKY_COLOR;
+use crate::ray::Ray;
+use crate::texture::TextureManager;
+use std::f64::consts::PI;
+
+// One layer of the sky, wrapped round the viewer like the inside of a cylinder
+pub struct SkyLayer {
+    pub texture_name: String,
+    // How many times the texture repeats round the full 360 degrees
+    pub repeats: f64,
+    // How far the layer turns when the viewer turns, 1.0 being fixed to the world. Layers meant
+    // to look nearer turn further. parallax * repeats should be a whole number, or the layer
+    // jumps when the view angle wraps round.
+    pub parallax: f64,
+    // Steady sideways drift in radians per second, for clouds
+    pub drift: f64,
+    // Height of the layer and how far its bottom edge sits above the horizon, both as fractions
+    // of the frame height
+    pub height: f64,
+    pub lift: f64,
+}
+
+// Panoramic sky made of layers drawn back to front. The first layer is the backdrop and fills
+// everything above the horizon; later ones are drawn over it and should have transparent gaps.
+pub struct Sky {
+    pub layers: Vec<SkyLayer>,
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sky {
+    pub fn new() -> Self {
+        Sky {
+            layers: vec![
+                SkyLayer { texture_name: "sky".to_string(), repeats: 1.0, parallax: 1.0, drift: 0.0, height: 0.5, lift: 0.0 },
+                SkyLayer { texture_name: "sky_hills".to_string(), repeats: 4.0, parallax: 1.25, drift: 0.0, height: 0.12, lift: 0.0 },
+            ],
+        }
+    }
+
+    // Draws the sky above `horizon`. Every column samples the sky at its own ray's angle, so the
+    // texture covers exactly 360 degrees whatever the frame width and field of view.
+    pub fn render(&self, frame: &mut FrameBuffer, texture_manager: &TextureManager, rays: &[Ray], view_angle: f64, horizon: i32, time: f64) {
+        let sky_rows = horizon.clamp(0, frame.height as i32);
+        if self.layers.is_empty() || texture_manager.get_texture(&self.layers[0].texture_name).is_none() {
+            frame.fill_rect(0, 0, frame.width, sky_rows as u32, SKY_COLOR);
+        }
+
+        for (index, layer) in self.layers.iter().enumerate() {
+            let Some(texture) = texture_manager.get_texture(&layer.texture_name) else {
+                continue;
+            };
+            let layer_height = (layer.height * frame.height as f64).max(1.0);
+            let bottom = horizon as f64 - layer.lift * frame.height as f64;
+            let top = bottom - layer_height;
+            // The backdrop stretches its top and bottom rows over whatever it doesn't cover
+            let (first_row, last_row) = if index == 0 {
+                (0, sky_rows)
+            } else {
+                (top.max(0.0) as i32, (bottom as i32).min(sky_rows))
+            };
+            let heading = view_angle * layer.parallax + layer.drift * time;
+
+            for (x, ray) in rays.iter().enumerate().take(frame.width as usize) {
+                let angle = heading + (ray.ray_angle - view_angle);
+                let turns = (angle / (2.0 * PI) * layer.repeats).rem_euclid(1.0);
+                let tex_x = ((turns * texture.width as f64) as u32).min(texture.width - 1);
+                for y in first_row..last_row {
+                    let v = ((y as f64 - top) / layer_height).clamp(0.0, 1.0);
+                    let tex_y = ((v * texture.height as f64) as u32).min(texture.height - 1);
+                    frame.put_pixel(x as i32, y, texture.sample(tex_x, tex_y));
+                }
+            }
+        }
+    }
+}
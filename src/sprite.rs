@@ -1,13 +1,20 @@
 use crate::globals::{WINDOW_WIDTH, WINDOW_HEIGHT};
+use crate::lighting::PointLight;
 
 #[derive(Clone)]
 pub struct Sprite {
     pub x: f64,
     pub y: f64,
+    // Height of the sprite's feet above the floor, for things that fly
+    pub z: f64,
+    // Direction the sprite faces, in radians. Only matters for sprites with rotation frames.
+    pub angle: f64,
     pub texture_name: String,
     pub visible: bool,
     pub animation_frame: u8,
     pub animation_timer: f32,
+    // Light the sprite gives off. Its position is ignored, the light follows the sprite.
+    pub light: Option<PointLight>,
 }
 
 impl Sprite {
@@ -15,10 +22,13 @@ impl Sprite {
         Sprite {
             x,
             y,
+            z: 0.0,
+            angle: 0.0,
             texture_name: texture_name.to_string(),
             visible: true,
             animation_frame: 0,
             animation_timer: 0.0,
+            light: None,
         }
     }
 
@@ -38,6 +48,19 @@ impl Sprite {
         (screen_x, screen_y)
     }
 
+    // Angle from the direction the sprite faces to the direction of the viewer, as seen from the
+    // sprite. 0 means the sprite is looking straight at the viewer. It grows counter-clockwise
+    // seen from above, the way DOOM numbers its rotations, which is against the way angles turn
+    // with y pointing down.
+    pub fn view_angle(&self, viewer_x: f64, viewer_y: f64) -> f64 {
+        let to_viewer = (viewer_y - self.y).atan2(viewer_x - self.x);
+        (self.angle - to_viewer).rem_euclid(2.0 * std::f64::consts::PI)
+    }
+
+    pub fn emitted_light(&self) -> Option<PointLight> {
+        self.light.map(|light| PointLight { x: self.x, y: self.y, ..light })
+    }
+
     pub fn update_animation(&mut self, delta_time: f32) {
         self.animation_timer += delta_time;
         if self.animation_timer > 0.1 {
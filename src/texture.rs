@@ -1,17 +1,147 @@
-use sdl3::render::{Texture, TextureCreator};
-use sdl3::video::WindowContext;
-use sdl3::image::LoadTexture;
+use crate::framebuffer::RGBA32;
+use sdl3::image::LoadSurface;
+use sdl3::surface::Surface;
 use std::collections::HashMap;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-pub struct TextureManager<'a> {
-    texture_creator: &'a TextureCreator<WindowContext>,
-    textures: HashMap<String, Texture<'a>>,
+// Image decoded into tightly packed RGBA bytes. Loaded through an SDL surface rather than a
+// texture, so it needs no window or renderer.
+#[derive(Clone)]
+pub struct PixelTexture {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<u8>,
 }
-impl<'a> TextureManager<'a> {
-    pub fn new(texture_creator: &'a TextureCreator<WindowContext>) -> Result<Self, String> {
+
+impl PixelTexture {
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let surface = Surface::from_file(path)
+            .and_then(|s| s.convert_format(RGBA32.into()))
+            .map_err(|e| e.to_string())?;
+        let (width, height) = surface.size();
+        let pitch = surface.pitch() as usize;
+        let row_bytes = width as usize * 4;
+
+        let mut pixels = Vec::with_capacity(row_bytes * height as usize);
+        surface.with_lock(|data| {
+            for row in data.chunks(pitch).take(height as usize) {
+                pixels.extend_from_slice(&row[..row_bytes]);
+            }
+        });
+        Ok(PixelTexture { width, height, pixels })
+    }
+
+    // Copy of the texture cut into a grate: `bars` vertical bars plus a rail along the top and
+    // bottom, with everything in between made transparent
+    pub fn grate(&self, bars: u32) -> PixelTexture {
+        let mut pixels = self.pixels.clone();
+        let cell = (self.width / bars.max(1)).max(1);
+        let rail = (self.height / 16).max(1);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let is_bar = x % cell < cell / 4;
+                let is_rail = !(rail..self.height - rail).contains(&y);
+                if !is_bar && !is_rail {
+                    pixels[((y * self.width + x) * 4 + 3) as usize] = 0;
+                }
+            }
+        }
+        PixelTexture { width: self.width, height: self.height, pixels }
+    }
+
+    // Copy of the texture moved down by `dy` pixels, wrapping round, for scrolling animations
+    pub fn scrolled(&self, dy: u32) -> PixelTexture {
+        let row_bytes = self.width as usize * 4;
+        let split = (self.height - dy % self.height.max(1)) as usize * row_bytes;
+        let mut pixels = Vec::with_capacity(self.pixels.len());
+        pixels.extend_from_slice(&self.pixels[split..]);
+        pixels.extend_from_slice(&self.pixels[..split]);
+        PixelTexture { width: self.width, height: self.height, pixels }
+    }
+
+    // One flat colour all over
+    pub fn solid(width: u32, height: u32, color: [u8; 3]) -> PixelTexture {
+        let pixels = [color[0], color[1], color[2], 255].repeat((width * height) as usize);
+        PixelTexture { width, height, pixels }
+    }
+
+    // Rolling hills against a transparent sky, for a sky layer. The ridge is made of whole sine
+    // waves across the width, so the texture tiles without a seam.
+    pub fn hills(width: u32, height: u32, color: [u8; 3]) -> PixelTexture {
+        let mut pixels = vec![0; (width * height * 4) as usize];
+        for x in 0..width {
+            let t = x as f64 / width as f64 * 2.0 * std::f64::consts::PI;
+            let ridge = 0.55 + 0.2 * (t * 2.0).sin() + 0.1 * (t * 5.0 + 1.0).sin() + 0.05 * (t * 11.0 + 2.0).sin();
+            let ridge_top = ((1.0 - ridge) * height as f64) as u32;
+            for y in ridge_top..height {
+                let i = ((y * width + x) * 4) as usize;
+                pixels[i..i + 3].copy_from_slice(&color);
+                pixels[i + 3] = 255;
+            }
+        }
+        PixelTexture { width, height, pixels }
+    }
+
+    // Round splat fading out at the edge, for decals. `ragged` makes the outline blotchy like
+    // a blood splat instead of a clean hole.
+    pub fn splat(size: u32, color: [u8; 3], ragged: bool) -> PixelTexture {
+        let mut pixels = vec![0; (size * size * 4) as usize];
+        let centre = size as f64 / 2.0;
+        for y in 0..size {
+            for x in 0..size {
+                let dx = x as f64 + 0.5 - centre;
+                let dy = y as f64 + 0.5 - centre;
+                let angle = dy.atan2(dx);
+                let radius = if ragged {
+                    centre * (0.7 + 0.15 * (angle * 5.0).sin() + 0.1 * (angle * 11.0 + 1.0).sin())
+                } else {
+                    centre * 0.8
+                };
+                let edge = 1.0 - (dx * dx + dy * dy).sqrt() / radius;
+                if edge > 0.0 {
+                    let i = ((y * size + x) * 4) as usize;
+                    pixels[i..i + 3].copy_from_slice(&color);
+                    pixels[i + 3] = ((edge * 4.0).min(1.0) * 255.0) as u8;
+                }
+            }
+        }
+        PixelTexture { width: size, height: size, pixels }
+    }
+
+    // Coordinates are clamped to the image, so callers can pass slightly out of range values
+    pub fn sample(&self, x: u32, y: u32) -> [u8; 4] {
+        let x = x.min(self.width.saturating_sub(1)) as usize;
+        let y = y.min(self.height.saturating_sub(1)) as usize;
+        let i = (y * self.width as usize + x) * 4;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+}
+
+pub struct TextureManager {
+    textures: HashMap<String, PixelTexture>,
+    // Number of rotation frames for sprites loaded as rotation sets, keyed by sprite name.
+    // Frame n (1-based) is stored as "<name>_rot<n>".
+    sprite_rotations: HashMap<String, usize>,
+    // Wall (and flat) textures indexed by wall id, looked up for every wall column and flat pixel
+    walls: Vec<WallFrames>,
+}
+
+// The frames of one wall type, a single frame for walls that don't animate. No frames means the
+// type has no texture.
+#[derive(Default)]
+struct WallFrames {
+    frames: Vec<PixelTexture>,
+    fps: f64,
+}
+
+// Frame rate for wall animations loaded from a folder of frames
+const WALL_ANIMATION_FPS: f64 = 8.0;
+impl TextureManager {
+    pub fn new() -> Result<Self, String> {
+        let mut texture_manager = TextureManager::empty();
         let mut textures = HashMap::new();
+        let mut sprite_rotations = HashMap::new();
        
         // Get project root path - works with cargo run
         let project_root = if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
@@ -31,14 +161,46 @@ impl<'a> TextureManager<'a> {
         // Load wall textures
         for i in 1..=5 {
             let path = project_root.join("src").join("resources").join("textures").join(format!("{}.png", i));
-            let texture = texture_creator.load_texture(&path)
+            let texture = PixelTexture::from_file(&path)
                 .map_err(|e| format!("Failed to load texture {}: {}", path.display(), e))?;
-            textures.insert(format!("wall_{}", i), texture);
+            texture_manager.add_wall_texture(i, texture);
         }
+
+        // Animated walls: a folder named after the wall id holding numbered frames, textures/<id>/0.png, 1.png, ...
+        for id in 1..=u8::MAX {
+            let dir = project_root.join("src").join("resources").join("textures").join(id.to_string());
+            let mut frames = Vec::new();
+            while dir.join(format!("{}.png", frames.len())).exists() {
+                let path = dir.join(format!("{}.png", frames.len()));
+                frames.push(PixelTexture::from_file(&path)
+                    .map_err(|e| format!("Failed to load wall frame {}: {}", path.display(), e))?);
+            }
+            if !frames.is_empty() {
+                texture_manager.add_wall_animation(id, frames, WALL_ANIMATION_FPS);
+            }
+        }
+
+        // Without lava artwork in textures/8, wall 8 is wall 2 slowly flowing downwards
+        if texture_manager.walls[8].frames.is_empty() {
+            let lava = &texture_manager.walls[2].frames[0];
+            let lava_frames = (0..16).map(|frame| lava.scrolled(frame * lava.height / 16)).collect();
+            texture_manager.add_wall_animation(8, lava_frames, 4.0);
+        }
+
+        // There's no fence artwork yet, so the see-through wall type is a grate cut out of wall 4
+        let grate = texture_manager.walls[4].frames[0].grate(8);
+        texture_manager.add_wall_texture(6, grate);
+
+        // Distant hills in front of the sky
+        textures.insert("sky_hills".to_string(), PixelTexture::hills(1024, 128, [50, 70, 60]));
+
+        // Decals are simple enough to draw here rather than ship as images
+        textures.insert("decal_bullet_hole".to_string(), PixelTexture::splat(32, [20, 20, 20], false));
+        textures.insert("decal_blood".to_string(), PixelTexture::splat(64, [120, 0, 0], true));
        
         // Load weapon texture
         let weapon_path = project_root.join("src").join("resources").join("sprites").join("weapon").join("shotgun").join("0.png");
-        let weapon_texture = texture_creator.load_texture(&weapon_path)
+        let weapon_texture = PixelTexture::from_file(&weapon_path)
             .map_err(|e| format!("Failed to load weapon texture: {}", e))?;
         textures.insert("weapon".to_string(), weapon_texture);
        
@@ -48,10 +210,25 @@ impl<'a> TextureManager<'a> {
         ];
        
         for enemy in enemy_textures {
-            let path = project_root.join("src").join("resources").join("sprites").join("npc").join(enemy).join("idle").join("0.png");
-            let texture = texture_creator.load_texture(&path)
+            let dir = project_root.join("src").join("resources").join("sprites").join("npc").join(enemy).join("idle");
+            let name = format!("enemy_{}", enemy);
+            let rotations = load_sprite(&mut textures, &dir, &name)
                 .map_err(|e| format!("Failed to load enemy texture: {}", e))?;
-            textures.insert(format!("enemy_{}", enemy), texture);
+            if rotations > 1 {
+                sprite_rotations.insert(name, rotations);
+            }
+        }
+       
+        // Load the lamps, the first frame of each
+        let lamp_sprites = [
+            "red_light", "green_light"
+        ];
+
+        for lamp in lamp_sprites {
+            let path = project_root.join("src").join("resources").join("sprites").join("animated_sprites").join(lamp).join("0.png");
+            let texture = PixelTexture::from_file(&path)
+                .map_err(|e| format!("Failed to load sprite {}: {}", path.display(), e))?;
+            textures.insert(lamp.to_string(), texture);
         }
        
         // Load UI textures
@@ -61,14 +238,122 @@ impl<'a> TextureManager<'a> {
        
         for ui in ui_textures {
             let path = project_root.join("src").join("resources").join("textures").join(format!("{}.png", ui));
-            let texture = texture_creator.load_texture(&path)
+            let texture = PixelTexture::from_file(&path)
                 .map_err(|e| format!("Failed to load UI texture: {}", e))?;
             textures.insert(ui.to_string(), texture);
         }
        
-        Ok(TextureManager { texture_creator, textures })
+        texture_manager.textures = textures;
+        texture_manager.sprite_rotations = sprite_rotations;
+        Ok(texture_manager)
+    }
+
+    // A manager with no textures at all, to be filled with add_texture. Needs no image files or
+    // SDL, e.g. for checking maps or rendering with stand-in textures.
+    pub fn empty() -> Self {
+        TextureManager {
+            textures: HashMap::new(),
+            sprite_rotations: HashMap::new(),
+            walls: (0..=u8::MAX).map(|_| WallFrames::default()).collect(),
+        }
+    }
+
+    pub fn add_texture(&mut self, name: &str, texture: PixelTexture) {
+        self.textures.insert(name.to_string(), texture);
     }
-    pub fn get_texture_mut(&mut self, name: &str) -> Option<&mut Texture<'a>> {
-        self.textures.get_mut(name)
+
+    // Sets a still texture for wall (and flat) type `id`
+    pub fn add_wall_texture(&mut self, id: u8, texture: PixelTexture) {
+        self.add_wall_animation(id, vec![texture], 0.0);
+    }
+
+    // Makes wall type `id` cycle through `frames` at `fps`
+    pub fn add_wall_animation(&mut self, id: u8, frames: Vec<PixelTexture>, fps: f64) {
+        self.walls[id as usize] = WallFrames { frames, fps };
+    }
+
+    // Texture for wall (and flat) type `id` at `time` seconds of game time. Animated walls are
+    // timed from the same clock, so every cell of a type shows the same frame.
+    pub fn get_wall_texture(&self, id: u8, time: f64) -> Option<&PixelTexture> {
+        let wall = &self.walls[id as usize];
+        match wall.frames.len() {
+            0 | 1 => wall.frames.first(),
+            count => wall.frames.get((time.max(0.0) * wall.fps) as usize % count),
+        }
     }
-}
\ No newline at end of file
+    pub fn get_texture(&self, name: &str) -> Option<&PixelTexture> {
+        self.textures.get(name)
+    }
+
+    // Picks the rotation frame for `view_angle`, the angle from the sprite's facing direction
+    // to the viewer (see Sprite::view_angle). Rotation 1 is the sprite facing the viewer and the
+    // rest go round the sprite in order of increasing angle, so rotation 3 shows its left side
+    // as in DOOM. Single-image sprites ignore the angle.
+    pub fn get_sprite_texture(&self, name: &str, view_angle: f64) -> Option<&PixelTexture> {
+        match self.sprite_rotations.get(name) {
+            Some(&count) => {
+                let step = 2.0 * std::f64::consts::PI / count as f64;
+                let index = ((view_angle.rem_euclid(2.0 * std::f64::consts::PI) + step / 2.0) / step) as usize % count;
+                self.textures.get(&format!("{}_rot{}", name, index + 1))
+            }
+            None => self.textures.get(name),
+        }
+    }
+}
+
+// Loads a sprite from `dir`. If the directory holds a rotation set named 0_1.png, 0_2.png, ...
+// (up to 0_8.png) every rotation is loaded as "<name>_rot<n>" and the first one also as plain
+// "<name>". Otherwise the single image 0.png is used. Returns how many rotations were loaded.
+fn load_sprite(textures: &mut HashMap<String, PixelTexture>, dir: &Path, name: &str) -> Result<usize, String> {
+    let mut rotations = 0;
+    while rotations < 8 {
+        let path = dir.join(format!("0_{}.png", rotations + 1));
+        if !path.exists() {
+            break;
+        }
+        let texture = PixelTexture::from_file(&path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        rotations += 1;
+        textures.insert(format!("{}_rot{}", name, rotations), texture);
+    }
+
+    let path = if rotations > 0 { dir.join("0_1.png") } else { dir.join("0.png") };
+    let texture = PixelTexture::from_file(&path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    textures.insert(name.to_string(), texture);
+    Ok(rotations.max(1))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sprite::Sprite;
+
+    #[test]
+    fn wall_textures_by_id_and_time() {
+        let mut texture_manager = TextureManager::empty();
+        texture_manager.add_wall_texture(1, PixelTexture::solid(1, 1, [10, 0, 0]));
+        let frames = (0..4).map(|frame| PixelTexture::solid(1, 1, [0, frame, 0])).collect();
+        texture_manager.add_wall_animation(8, frames, 4.0);
+
+        let green = |time| texture_manager.get_wall_texture(8, time).unwrap().sample(0, 0)[1];
+        assert_eq!([green(0.0), green(0.3), green(0.5), green(0.99), green(1.0)], [0, 1, 2, 3, 0]);
+        assert_eq!(texture_manager.get_wall_texture(1, 12.3).unwrap().sample(0, 0)[0], 10);
+        assert!(texture_manager.get_wall_texture(2, 0.0).is_none());
+    }
+
+    #[test]
+    fn rotation_frames_go_round_like_doom() {
+        let mut texture_manager = TextureManager::empty();
+        for rotation in 1..=8 {
+            texture_manager.add_texture(&format!("imp_rot{}", rotation), PixelTexture::solid(1, 1, [rotation, 0, 0]));
+        }
+        texture_manager.sprite_rotations.insert("imp".to_string(), 8);
+        // Facing east, seen from the east, north-east, north, west and south
+        let imp = Sprite::new(0.0, 0.0, "imp");
+        let rotation = |x, y| texture_manager.get_sprite_texture("imp", imp.view_angle(x, y)).unwrap().sample(0, 0)[0];
+        assert_eq!(
+            [rotation(10.0, 0.0), rotation(10.0, -10.0), rotation(0.0, -10.0), rotation(-10.0, 0.0), rotation(0.0, 10.0)],
+            [1, 2, 3, 5, 7],
+        );
+    }
+}
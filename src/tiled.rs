@@ -0,0 +1,584 @@
+use crate::globals::TILE_SIZE;
+use crate::lighting::PointLight;
+use crate::map::{Entity, Map, Spawn};
+use crate::map_file::DEFAULT_FLOOR;
+use roxmltree::Node;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+// Importer for maps drawn in the Tiled editor (https://www.mapeditor.org), saved as TMX or JSON.
+//
+// Tile layers named `walls`, `floor` and `ceiling` become the grid and the flats. Each tile is
+// drawn with wall_N, where N is, in order of preference: the tile's `texture` property ("wall_3"),
+// the number in the name of the tile's image file (the engine's own textures/3.png), or the tile's
+// place in its tileset counting from 1.
+//
+// Objects of type (class) `player` set the start. Any other object places the sprite it's named
+// after, e.g. `enemy_soldier`, with optional `z`, `light_radius` (both in tiles) and
+// `light_intensity` properties. Both face their `angle` property or their rotation in degrees.
+
+// Tiled keeps flip and rotation flags in the top bits of a tile's GID
+const FLIP_FLAGS: u32 = 0xF000_0000;
+
+struct Tile {
+    texture: Option<String>,
+    image: Option<String>,
+}
+
+struct Tileset {
+    first_gid: u32,
+    name: String,
+    tile_count: Option<u32>,
+    tiles: HashMap<u32, Tile>,
+}
+
+impl Tileset {
+    fn texture_id(&self, local: u32) -> Result<u8, String> {
+        let tile = self.tiles.get(&local);
+        let id = if let Some(texture) = tile.and_then(|tile| tile.texture.as_deref()) {
+            texture.strip_prefix("wall_")
+                .and_then(|number| number.parse().ok())
+                .ok_or_else(|| format!("has texture '{}', expected a name like wall_3", texture))?
+        } else if let Some(number) = tile
+            .and_then(|tile| tile.image.as_deref())
+            .and_then(|image| Path::new(image).file_stem()?.to_str()?.parse().ok()) {
+            number
+        } else {
+            local + 1
+        };
+        u8::try_from(id).ok()
+            .filter(|&id| id > 0)
+            .ok_or_else(|| format!("maps to wall_{}, but wall textures go from wall_1 to wall_255", id))
+    }
+}
+
+struct TileLayer {
+    name: String,
+    data: Vec<u32>,
+}
+
+struct Object {
+    id: u32,
+    name: String,
+    kind: String,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    rotation: f64,
+    // Tile objects hang up from their position rather than down
+    tile: bool,
+    properties: HashMap<String, String>,
+}
+
+impl Object {
+    // Middle of the object in Tiled's pixels
+    fn centre(&self) -> (f64, f64) {
+        let y = if self.tile { self.y - self.height / 2.0 } else { self.y + self.height / 2.0 };
+        (self.x + self.width / 2.0, y)
+    }
+
+    // Direction the object faces in radians. Tiled turns objects clockwise on screen, the same way
+    // the engine's angles go with y pointing down, so the rotation carries over as it is.
+    fn facing(&self, problems: &mut Vec<String>) -> f64 {
+        self.number("angle", problems).unwrap_or(self.rotation).to_radians()
+    }
+
+    fn number(&self, name: &str, problems: &mut Vec<String>) -> Option<f64> {
+        let value = self.properties.get(name)?;
+        let number = value.parse().ok();
+        if number.is_none() {
+            problems.push(format!("object {} ('{}'): property {} is '{}', expected a number, it's ignored", self.id, self.name, name, value));
+        }
+        number
+    }
+}
+
+// What's read out of either file format
+struct TiledMap {
+    width: usize,
+    height: usize,
+    tile_width: f64,
+    tile_height: f64,
+    tilesets: Vec<Tileset>,
+    layers: Vec<TileLayer>,
+    objects: Vec<Object>,
+}
+
+impl TiledMap {
+    fn texture_id(&self, gid: u32) -> Result<u8, String> {
+        let gid = gid & !FLIP_FLAGS;
+        let tileset = self.tilesets.iter()
+            .filter(|tileset| tileset.first_gid <= gid)
+            .max_by_key(|tileset| tileset.first_gid)
+            .ok_or("isn't in any tileset")?;
+        let local = gid - tileset.first_gid;
+        if tileset.tile_count.is_some_and(|count| local >= count) {
+            return Err("isn't in any tileset".to_string());
+        }
+        tileset.texture_id(local)
+            .map_err(|e| format!("(tile {} of tileset '{}') {}", local, tileset.name, e))
+    }
+
+    // A tile layer as texture ids. Each unknown tile is reported once, at the first cell it's used in.
+    fn layer_grid(&self, layer: &TileLayer, problems: &mut Vec<String>) -> Vec<Vec<u8>> {
+        let mut grid = vec![vec![0; self.width]; self.height];
+        if layer.data.len() != self.width * self.height {
+            problems.push(format!("layer '{}' has {} tiles, expected {} for a {}x{} map", layer.name, layer.data.len(), self.width * self.height, self.width, self.height));
+            return grid;
+        }
+        let mut reported = HashSet::new();
+        for (index, &gid) in layer.data.iter().enumerate() {
+            if gid == 0 {
+                continue;
+            }
+            let (col, row) = (index % self.width, index / self.width);
+            match self.texture_id(gid) {
+                Ok(id) => grid[row][col] = id,
+                Err(e) => {
+                    if reported.insert(gid) {
+                        problems.push(format!("layer '{}', cell ({}, {}): tile id {} {}", layer.name, col, row, gid & !FLIP_FLAGS, e));
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    // The map and any warnings, things that were left out but don't stop it being played, or every
+    // error that does
+    fn into_map(self) -> Result<(Map, Vec<String>), Vec<String>> {
+        let tile = TILE_SIZE as f64;
+        let (mut errors, mut warnings) = (Vec::new(), Vec::new());
+
+        let (mut grid, mut floor, mut ceiling) = (None, None, None);
+        for layer in &self.layers {
+            let target = match layer.name.to_lowercase().as_str() {
+                "walls" | "wall" => &mut grid,
+                "floor" | "floors" => &mut floor,
+                "ceiling" | "ceilings" => &mut ceiling,
+                _ => {
+                    warnings.push(format!("tile layer '{}' isn't used, tile layers should be named walls, floor or ceiling", layer.name));
+                    continue;
+                }
+            };
+            if target.is_some() {
+                warnings.push(format!("second tile layer named '{}' isn't used", layer.name));
+                continue;
+            }
+            *target = Some(self.layer_grid(layer, &mut errors));
+        }
+        let grid = grid.unwrap_or_else(|| {
+            errors.push("no walls tile layer".to_string());
+            vec![vec![0; self.width]; self.height]
+        });
+        let floor = floor.unwrap_or_else(|| vec![vec![DEFAULT_FLOOR; self.width]; self.height]);
+        let ceiling = ceiling.unwrap_or_else(|| vec![vec![0; self.width]; self.height]);
+        let light = vec![vec![255; self.width]; self.height];
+        let mut map = Map::with_layers(&grid, &floor, &ceiling, &light);
+
+        let mut spawn = None;
+        for object in &self.objects {
+            let (x, y) = object.centre();
+            let (x, y) = (x / self.tile_width * tile, y / self.tile_height * tile);
+            if object.kind.eq_ignore_ascii_case("player") {
+                if spawn.is_some() {
+                    warnings.push(format!("object {} is a second player start, the last one is used", object.id));
+                }
+                spawn = Some(Spawn { x, y, angle: object.facing(&mut warnings) });
+            } else if object.name.is_empty() {
+                warnings.push(format!("object {} has no name and is left out, name it after the sprite it places (e.g. enemy_soldier)", object.id));
+            } else {
+                let mut entity = Entity::new(&object.name, x, y);
+                entity.angle = object.facing(&mut warnings);
+                if let Some(z) = object.number("z", &mut warnings) {
+                    entity.z = z * tile;
+                }
+                let radius = object.number("light_radius", &mut warnings);
+                let intensity = object.number("light_intensity", &mut warnings);
+                if radius.is_some() || intensity.is_some() {
+                    entity.light = Some(PointLight::new(0.0, 0.0, radius.unwrap_or(4.0) * tile, intensity.unwrap_or(0.8)));
+                }
+                map.entities.push(entity);
+            }
+        }
+        match spawn {
+            Some(spawn) => map.spawn = spawn,
+            None => errors.push("no player start, add an object of type player".to_string()),
+        }
+
+        if errors.is_empty() { Ok((map, warnings)) } else { Err(errors) }
+    }
+}
+
+fn json_str<'a>(value: &'a Value, key: &str) -> &'a str {
+    value.get(key).and_then(Value::as_str).unwrap_or("")
+}
+
+fn json_f64(value: &Value, key: &str) -> f64 {
+    value.get(key).and_then(Value::as_f64).unwrap_or(0.0)
+}
+
+fn json_u32(value: &Value, key: &str, context: &str) -> Result<u32, String> {
+    value.get(key)
+        .and_then(Value::as_u64)
+        .and_then(|number| u32::try_from(number).ok())
+        .ok_or_else(|| format!("{} has no '{}'", context, key))
+}
+
+fn json_properties(value: &Value) -> HashMap<String, String> {
+    let properties = value.get("properties").and_then(Value::as_array).map_or(&[][..], Vec::as_slice);
+    properties.iter()
+        .map(|property| {
+            let value = match property.get("value") {
+                Some(Value::String(text)) => text.clone(),
+                Some(other) => other.to_string(),
+                None => String::new(),
+            };
+            (json_str(property, "name").to_string(), value)
+        })
+        .collect()
+}
+
+fn json_tileset(value: &Value, first_gid: u32) -> Tileset {
+    let tiles = value.get("tiles").and_then(Value::as_array).map_or(&[][..], Vec::as_slice);
+    Tileset {
+        first_gid,
+        name: json_str(value, "name").to_string(),
+        tile_count: value.get("tilecount").and_then(Value::as_u64).map(|count| count as u32),
+        tiles: tiles.iter()
+            .filter_map(|tile| {
+                let id = tile.get("id").and_then(Value::as_u64)? as u32;
+                let texture = json_properties(tile).remove("texture");
+                let image = tile.get("image").and_then(Value::as_str).map(str::to_string);
+                Some((id, Tile { texture, image }))
+            })
+            .collect(),
+    }
+}
+
+fn read_json_layers(layers: &[Value], map: &mut TiledMap) -> Result<(), String> {
+    for layer in layers {
+        let name = json_str(layer, "name");
+        match json_str(layer, "type") {
+            "tilelayer" => {
+                if layer.get("chunks").is_some() {
+                    return Err(format!("layer '{}' is from an infinite map, which isn't supported", name));
+                }
+                if let Some(encoding) = layer.get("encoding").and_then(Value::as_str).filter(|&encoding| encoding != "csv") {
+                    return Err(format!("layer '{}' is {} encoded, save the map with the CSV tile layer format", name, encoding));
+                }
+                let data = layer.get("data").and_then(Value::as_array)
+                    .ok_or_else(|| format!("layer '{}' has no tile data", name))?
+                    .iter()
+                    .map(|gid| gid.as_u64().map(|gid| gid as u32).ok_or_else(|| format!("layer '{}' has a tile that isn't a number", name)))
+                    .collect::<Result<_, _>>()?;
+                map.layers.push(TileLayer { name: name.to_string(), data });
+            }
+            "objectgroup" => {
+                for object in layer.get("objects").and_then(Value::as_array).map_or(&[][..], Vec::as_slice) {
+                    let kind = if json_str(object, "type").is_empty() { json_str(object, "class") } else { json_str(object, "type") };
+                    map.objects.push(Object {
+                        id: json_u32(object, "id", "object")?,
+                        name: json_str(object, "name").to_string(),
+                        kind: kind.to_string(),
+                        x: json_f64(object, "x"),
+                        y: json_f64(object, "y"),
+                        width: json_f64(object, "width"),
+                        height: json_f64(object, "height"),
+                        rotation: json_f64(object, "rotation"),
+                        tile: object.get("gid").is_some(),
+                        properties: json_properties(object),
+                    });
+                }
+            }
+            "group" => read_json_layers(layer.get("layers").and_then(Value::as_array).map_or(&[][..], Vec::as_slice), map)?,
+            // Image layers have nothing the engine can use
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn read_json(text: &str, dir: &Path) -> Result<TiledMap, String> {
+    let root: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    if root.get("infinite").and_then(Value::as_bool) == Some(true) {
+        return Err("infinite maps aren't supported".to_string());
+    }
+    let mut map = TiledMap {
+        width: json_u32(&root, "width", "the map")? as usize,
+        height: json_u32(&root, "height", "the map")? as usize,
+        tile_width: json_u32(&root, "tilewidth", "the map")? as f64,
+        tile_height: json_u32(&root, "tileheight", "the map")? as f64,
+        tilesets: Vec::new(),
+        layers: Vec::new(),
+        objects: Vec::new(),
+    };
+    for tileset in root.get("tilesets").and_then(Value::as_array).map_or(&[][..], Vec::as_slice) {
+        let first_gid = json_u32(tileset, "firstgid", "a tileset")?;
+        let tileset = match tileset.get("source").and_then(Value::as_str) {
+            Some(source) => read_external_tileset(&dir.join(source), first_gid)?,
+            None => json_tileset(tileset, first_gid),
+        };
+        map.tilesets.push(tileset);
+    }
+    read_json_layers(root.get("layers").and_then(Value::as_array).map_or(&[][..], Vec::as_slice), &mut map)?;
+    Ok(map)
+}
+
+fn xml_u32(node: Node, name: &str) -> Result<u32, String> {
+    node.attribute(name)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("<{}> at {} has no whole number '{}'", node.tag_name().name(), node.document().text_pos_at(node.range().start), name))
+}
+
+fn xml_f64(node: Node, name: &str) -> f64 {
+    node.attribute(name).and_then(|value| value.parse().ok()).unwrap_or(0.0)
+}
+
+fn xml_children<'a, 'input>(node: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |child| child.has_tag_name(name))
+}
+
+fn xml_properties(node: Node) -> HashMap<String, String> {
+    xml_children(node, "properties")
+        .flat_map(|properties| xml_children(properties, "property"))
+        .map(|property| {
+            let value = property.attribute("value").or_else(|| property.text()).unwrap_or("");
+            (property.attribute("name").unwrap_or("").to_string(), value.to_string())
+        })
+        .collect()
+}
+
+fn xml_tileset(node: Node, first_gid: u32) -> Tileset {
+    Tileset {
+        first_gid,
+        name: node.attribute("name").unwrap_or("").to_string(),
+        tile_count: node.attribute("tilecount").and_then(|count| count.parse().ok()),
+        tiles: xml_children(node, "tile")
+            .filter_map(|tile| {
+                let id = tile.attribute("id")?.parse().ok()?;
+                let texture = xml_properties(tile).remove("texture");
+                let image = xml_children(tile, "image").next()
+                    .and_then(|image| image.attribute("source"))
+                    .map(str::to_string);
+                Some((id, Tile { texture, image }))
+            })
+            .collect(),
+    }
+}
+
+fn read_xml_layers(node: Node, map: &mut TiledMap) -> Result<(), String> {
+    for layer in node.children().filter(Node::is_element) {
+        let name = layer.attribute("name").unwrap_or("");
+        match layer.tag_name().name() {
+            "layer" => {
+                let data = xml_children(layer, "data").next()
+                    .ok_or_else(|| format!("layer '{}' has no tile data", name))?;
+                if xml_children(data, "chunk").next().is_some() {
+                    return Err(format!("layer '{}' is from an infinite map, which isn't supported", name));
+                }
+                let data = match data.attribute("encoding") {
+                    Some("csv") => data.text().unwrap_or("")
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|gid| !gid.is_empty())
+                        .map(|gid| gid.parse().map_err(|_| format!("layer '{}' has a tile '{}' that isn't a number", name, gid)))
+                        .collect::<Result<_, _>>()?,
+                    None => xml_children(data, "tile")
+                        .map(|tile| tile.attribute("gid").and_then(|gid| gid.parse().ok()).unwrap_or(0))
+                        .collect(),
+                    Some(encoding) => return Err(format!("layer '{}' is {} encoded, save the map with the CSV tile layer format", name, encoding)),
+                };
+                map.layers.push(TileLayer { name: name.to_string(), data });
+            }
+            "objectgroup" => {
+                for object in xml_children(layer, "object") {
+                    let kind = object.attribute("type").or_else(|| object.attribute("class")).unwrap_or("");
+                    map.objects.push(Object {
+                        id: xml_u32(object, "id")?,
+                        name: object.attribute("name").unwrap_or("").to_string(),
+                        kind: kind.to_string(),
+                        x: xml_f64(object, "x"),
+                        y: xml_f64(object, "y"),
+                        width: xml_f64(object, "width"),
+                        height: xml_f64(object, "height"),
+                        rotation: xml_f64(object, "rotation"),
+                        tile: object.attribute("gid").is_some(),
+                        properties: xml_properties(object),
+                    });
+                }
+            }
+            "group" => read_xml_layers(layer, map)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn read_tmx(text: &str, dir: &Path) -> Result<TiledMap, String> {
+    let document = roxmltree::Document::parse(text).map_err(|e| e.to_string())?;
+    let root = document.root_element();
+    if root.attribute("infinite") == Some("1") {
+        return Err("infinite maps aren't supported".to_string());
+    }
+    let mut map = TiledMap {
+        width: xml_u32(root, "width")? as usize,
+        height: xml_u32(root, "height")? as usize,
+        tile_width: xml_u32(root, "tilewidth")? as f64,
+        tile_height: xml_u32(root, "tileheight")? as f64,
+        tilesets: Vec::new(),
+        layers: Vec::new(),
+        objects: Vec::new(),
+    };
+    for tileset in xml_children(root, "tileset") {
+        let first_gid = xml_u32(tileset, "firstgid")?;
+        let tileset = match tileset.attribute("source") {
+            Some(source) => read_external_tileset(&dir.join(source), first_gid)?,
+            None => xml_tileset(tileset, first_gid),
+        };
+        map.tilesets.push(tileset);
+    }
+    read_xml_layers(root, &mut map)?;
+    Ok(map)
+}
+
+// Tilesets saved in their own file, as TSX or JSON
+fn read_external_tileset(path: &Path, first_gid: u32) -> Result<Tileset, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read tileset {}: {}", path.display(), e))?;
+    if path.extension().is_some_and(|extension| extension == "tsx") {
+        let document = roxmltree::Document::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(xml_tileset(document.root_element(), first_gid))
+    } else {
+        let value: Value = serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(json_tileset(&value, first_gid))
+    }
+}
+
+// Tiled saves maps as .tmx, or .tmj / .json when exported as JSON
+pub fn is_tiled_map(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| matches!(extension, "tmx" | "tmj" | "json"))
+}
+
+impl Map {
+    // Reads a map saved by Tiled, along with warnings about anything left out. Every error found is
+    // reported, one per line.
+    pub fn import_tiled(path: &Path) -> Result<(Map, Vec<String>), String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read map {}: {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let tiled = if path.extension().is_some_and(|extension| extension == "tmx") {
+            read_tmx(&text, dir)
+        } else {
+            read_json(&text, dir)
+        };
+        tiled
+            .map_err(|e| format!("{}: {}", path.display(), e))?
+            .into_map()
+            .map_err(|problems| {
+                problems.iter()
+                    .map(|problem| format!("{}: {}", path.display(), problem))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 3x3 map with one tileset of four tiles: tile 0 names its texture, tile 1 has the engine's
+    // textures/3.png as its image and the rest fall back to their place in the tileset
+    fn tmx(walls: &str, extra: &str) -> String {
+        format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="3" height="3" tilewidth="32" tileheight="32" infinite="0">
+ <tileset firstgid="1" name="walls" tilewidth="32" tileheight="32" tilecount="4">
+  <tile id="0"><properties><property name="texture" value="wall_7"/></properties></tile>
+  <tile id="1"><image width="32" height="32" source="textures/3.png"/></tile>
+ </tileset>
+ <layer id="1" name="walls" width="3" height="3"><data encoding="csv">{}</data></layer>
+ <objectgroup id="2" name="things">
+  <object id="1" type="player" x="48" y="48"/>
+ </objectgroup>
+ {}
+</map>"#, walls, extra)
+    }
+
+    fn json(walls: &str) -> String {
+        format!(r#"{{
+ "width": 3, "height": 3, "tilewidth": 32, "tileheight": 32, "infinite": false,
+ "tilesets": [{{
+  "firstgid": 1, "name": "walls", "tilecount": 4,
+  "tiles": [
+   {{ "id": 0, "properties": [{{ "name": "texture", "type": "string", "value": "wall_7" }}] }},
+   {{ "id": 1, "image": "textures/3.png" }}
+  ]
+ }}],
+ "layers": [
+  {{ "type": "tilelayer", "name": "walls", "width": 3, "height": 3, "data": [{}] }},
+  {{ "type": "objectgroup", "name": "things", "objects": [{{ "id": 1, "type": "player", "x": 48, "y": 48 }}] }}
+ ]
+}}"#, walls)
+    }
+
+    fn grid(map: &Map) -> Vec<Vec<u8>> {
+        map.cells.iter().map(|row| row.iter().map(|cell| cell.wall).collect()).collect()
+    }
+
+    // 2147483649 is tile 1 flipped horizontally
+    const WALLS: &str = "1,2,3,\n2147483649,0,4,\n1,1,1";
+    const RESOLVED: [[u8; 3]; 3] = [[7, 3, 3], [7, 0, 4], [7, 7, 7]];
+
+    #[test]
+    fn tmx_tiles_resolve_to_wall_textures() {
+        let (map, warnings) = read_tmx(&tmx(WALLS, ""), Path::new(".")).unwrap().into_map().unwrap();
+        assert_eq!(grid(&map), RESOLVED);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        let tile = TILE_SIZE as f64;
+        assert_eq!((map.spawn.x, map.spawn.y), (1.5 * tile, 1.5 * tile));
+    }
+
+    #[test]
+    fn json_tiles_resolve_to_wall_textures() {
+        let (map, warnings) = read_json(&json(WALLS), Path::new(".")).unwrap().into_map().unwrap();
+        assert_eq!(grid(&map), RESOLVED);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn tiles_past_the_tilecount_are_reported_once() {
+        let errors = read_json(&json("5,1,5,1,0,1,1,1,5"), Path::new(".")).unwrap().into_map().err().unwrap();
+        assert_eq!(errors, ["layer 'walls', cell (0, 0): tile id 5 isn't in any tileset"]);
+    }
+
+    #[test]
+    fn leftovers_are_warnings() {
+        let extra = r#"<layer id="3" name="decor" width="3" height="3"><data encoding="csv">0,0,0,0,0,0,0,0,0</data></layer>
+ <objectgroup id="4" name="more"><object id="2" x="16" y="16"/></objectgroup>"#;
+        let (map, warnings) = read_tmx(&tmx(WALLS, extra), Path::new(".")).unwrap().into_map().unwrap();
+        assert_eq!(grid(&map), RESOLVED);
+        assert_eq!(warnings, [
+            "tile layer 'decor' isn't used, tile layers should be named walls, floor or ceiling",
+            "object 2 has no name and is left out, name it after the sprite it places (e.g. enemy_soldier)",
+        ]);
+        assert!(map.entities.is_empty());
+    }
+
+    #[test]
+    fn objects_face_their_rotation() {
+        let extra = r#"<objectgroup id="3" name="actors">
+  <object id="2" name="enemy_soldier" x="16" y="16" rotation="90"/>
+  <object id="3" name="enemy_soldier" x="16" y="16" rotation="90"><properties><property name="angle" value="180"/></properties></object>
+ </objectgroup>"#;
+        let (map, warnings) = read_tmx(&tmx(WALLS, extra), Path::new(".")).unwrap().into_map().unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        let angles: Vec<f64> = map.entities.iter().map(|entity| entity.angle).collect();
+        assert_eq!(angles, [90f64.to_radians(), 180f64.to_radians()]);
+    }
+}
@@ -0,0 +1,78 @@
+use crate::ray::{WallHit, WallSide};
+
+// A mapping of the map plane onto itself made of quarter turns, axis flips and a shift:
+// p' = matrix * p + offset. Mirrors and portals are both one of these, and so is any chain of them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    // Rows of the 2x2 matrix. Entries are only ever 0 and ±1, so chaining stays exact.
+    pub matrix: [[f64; 2]; 2],
+    pub offset: (f64, f64),
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Transform { matrix: [[1.0, 0.0], [0.0, 1.0]], offset: (0.0, 0.0) }
+    }
+
+    // Reflection in the wall face a ray hit
+    pub fn mirror(hit: &WallHit) -> Self {
+        match hit.side {
+            WallSide::West | WallSide::East => Transform { matrix: [[-1.0, 0.0], [0.0, 1.0]], offset: (2.0 * hit.x, 0.0) },
+            WallSide::North | WallSide::South => Transform { matrix: [[1.0, 0.0], [0.0, -1.0]], offset: (0.0, 2.0 * hit.y) },
+        }
+    }
+
+    // Turns by `quarter_turns` * 90 degrees, the same way rotation angles grow, then shifts by `offset`
+    pub fn rotation(quarter_turns: i32, offset: (f64, f64)) -> Self {
+        let matrix = match quarter_turns.rem_euclid(4) {
+            0 => [[1.0, 0.0], [0.0, 1.0]],
+            1 => [[0.0, -1.0], [1.0, 0.0]],
+            2 => [[-1.0, 0.0], [0.0, -1.0]],
+            _ => [[0.0, 1.0], [-1.0, 0.0]],
+        };
+        Transform { matrix, offset }
+    }
+
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        let (dx, dy) = self.apply_direction(x, y);
+        (dx + self.offset.0, dy + self.offset.1)
+    }
+
+    // Only the turning and flipping, for directions
+    pub fn apply_direction(&self, x: f64, y: f64) -> (f64, f64) {
+        let [[a, b], [c, d]] = self.matrix;
+        (a * x + b * y, c * x + d * y)
+    }
+
+    // Turns an angle the way apply_direction turns a direction
+    pub fn apply_angle(&self, angle: f64) -> f64 {
+        let (x, y) = self.apply_direction(angle.cos(), angle.sin());
+        y.atan2(x).rem_euclid(2.0 * std::f64::consts::PI)
+    }
+
+    // This transform applied after `first`
+    pub fn after(&self, first: &Transform) -> Transform {
+        let [[a, b], [c, d]] = self.matrix;
+        let [[e, f], [g, h]] = first.matrix;
+        Transform {
+            matrix: [[a * e + b * g, a * f + b * h], [c * e + d * g, c * f + d * h]],
+            offset: self.apply(first.offset.0, first.offset.1),
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        // The matrix is orthogonal, so its inverse is its transpose
+        let [[a, b], [c, d]] = self.matrix;
+        let (x, y) = self.offset;
+        Transform {
+            matrix: [[a, c], [b, d]],
+            offset: (-(a * x + c * y), -(b * x + d * y)),
+        }
+    }
+
+    // True if the transform swaps left and right, like an odd number of mirrors does
+    pub fn is_flipped(&self) -> bool {
+        let [[a, b], [c, d]] = self.matrix;
+        a * d - b * c < 0.0
+    }
+}
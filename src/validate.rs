@@ -0,0 +1,265 @@
+use crate::cell::CellFlags;
+use crate::globals::TILE_SIZE;
+use crate::map::Map;
+use crate::portal::Face;
+use crate::ray::WallSide;
+use crate::texture::TextureManager;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+// Problems found in a map. Errors break the map, warnings are worth a look but can be on purpose.
+#[derive(Default)]
+pub struct Report {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+const NEIGHBOURS: [(i32, i32); 4] = [(0, -1), (0, 1), (-1, 0), (1, 0)];
+
+pub fn validate(map: &Map, texture_manager: &TextureManager) -> Report {
+    let mut report = Report::default();
+    check_border(map, &mut report);
+    check_start(map, &mut report);
+    check_textures(map, texture_manager, &mut report);
+    check_entities(map, texture_manager, &mut report);
+    check_doors(map, &mut report);
+    check_reachable(map, &mut report);
+    report
+}
+
+fn cell_of(x: f64, y: f64) -> (i32, i32) {
+    let tile = TILE_SIZE as f64;
+    ((x / tile).floor() as i32, (y / tile).floor() as i32)
+}
+
+// Cells the player can walk through. Doors count, they can be opened.
+fn walkable(map: &Map, col: i32, row: i32) -> bool {
+    map.in_bounds(col, row) && {
+        let flags = map.cells[row as usize][col as usize].flags;
+        !flags.contains(CellFlags::SOLID) || flags.contains(CellFlags::DOOR)
+    }
+}
+
+// Rays that get past the edge of the map have nothing to hit, leaving holes in the view
+fn check_border(map: &Map, report: &mut Report) {
+    for (row, cells) in map.cells.iter().enumerate() {
+        for (col, cell) in cells.iter().enumerate() {
+            let (col, row) = (col as i32, row as i32);
+            let on_edge = NEIGHBOURS.iter().any(|&(dx, dy)| !map.in_bounds(col + dx, row + dy));
+            if !on_edge {
+                continue;
+            }
+            if walkable(map, col, row) {
+                report.errors.push(format!("cell ({}, {}) is open to the edge of the map, the border has to be closed", col, row));
+            } else if !cell.flags.contains(CellFlags::BLOCKS_SIGHT) {
+                report.errors.push(format!("see-through wall at ({}, {}) is on the edge of the map, rays go through it into nothing", col, row));
+            }
+        }
+    }
+}
+
+fn check_start(map: &Map, report: &mut Report) {
+    let (col, row) = cell_of(map.spawn.x, map.spawn.y);
+    if !map.in_bounds(col, row) {
+        report.errors.push(format!("the player start ({}, {}) is outside the map", col, row));
+    } else if map.has_wall_at(map.spawn.x, map.spawn.y) {
+        report.errors.push(format!("the player starts inside the wall at ({}, {})", col, row));
+    }
+}
+
+// Every wall face and flat needs its wall_N loaded. Mirror and portal faces show what's beyond
+// them instead, so they don't. Each missing texture is reported once.
+fn check_textures(map: &Map, texture_manager: &TextureManager, report: &mut Report) {
+    let mut missing: BTreeMap<u8, (String, usize)> = BTreeMap::new();
+    let mut check = |id: u8, use_of: &dyn Fn() -> String| {
+        if texture_manager.get_wall_texture(id, 0.0).is_none() {
+            missing.entry(id).or_insert_with(|| (use_of(), 0)).1 += 1;
+        }
+    };
+    for (row, cells) in map.cells.iter().enumerate() {
+        for (col, cell) in cells.iter().enumerate() {
+            if cell.flags.contains(CellFlags::SOLID) && !map.is_mirror(col, row) {
+                for side in [WallSide::North, WallSide::South, WallSide::East, WallSide::West] {
+                    if map.portals.contains_key(&Face::new(col, row, side)) {
+                        continue;
+                    }
+                    check(map.get_face_texture_id(col, row, side), &|| format!("the {:?} face of ({}, {})", side, col, row).to_lowercase());
+                }
+            }
+            if cell.floor > 0 {
+                check(cell.floor, &|| format!("the floor of ({}, {})", col, row));
+            }
+            if cell.ceiling > 0 {
+                check(cell.ceiling, &|| format!("the ceiling of ({}, {})", col, row));
+            }
+        }
+    }
+    for (id, (first, count)) in missing {
+        let more = if count > 1 { format!(" and {} more", count - 1) } else { String::new() };
+        report.errors.push(format!("wall_{} isn't loaded, it's used by {}{}", id, first, more));
+    }
+}
+
+fn check_entities(map: &Map, texture_manager: &TextureManager, report: &mut Report) {
+    let tile = TILE_SIZE as f64;
+    for entity in &map.entities {
+        let (col, row) = cell_of(entity.x, entity.y);
+        let name = &entity.texture_name;
+        let (x, y) = (entity.x / tile, entity.y / tile);
+        if !map.in_bounds(col, row) {
+            report.errors.push(format!("{} at ({}, {}) is outside the map", name, x, y));
+        } else if map.has_wall_at(entity.x, entity.y) {
+            report.errors.push(format!("{} at ({}, {}) is inside the solid cell ({}, {})", name, x, y, col, row));
+        }
+        if texture_manager.get_sprite_texture(name, 0.0).is_none() {
+            report.errors.push(format!("{} at ({}, {}) has no sprite texture", name, x, y));
+        }
+    }
+}
+
+// A door panel spans its cell between two walls. Without them on either side it hangs in the air.
+fn check_doors(map: &Map, report: &mut Report) {
+    let solid = |col: i32, row: i32| map.in_bounds(col, row) && map.is_solid(col as usize, row as usize);
+    let mut doors: Vec<_> = map.doors.keys().collect();
+    doors.sort_by_key(|&&(col, row)| (row, col));
+    for &(col, row) in doors {
+        let (col, row) = (col as i32, row as i32);
+        let east_west = solid(col - 1, row) && solid(col + 1, row);
+        let north_south = solid(col, row - 1) && solid(col, row + 1);
+        if !east_west && !north_south {
+            report.errors.push(format!("door at ({}, {}) has no frame, it needs walls on both sides", col, row));
+        }
+    }
+}
+
+// Open areas the player can't get to from the start, walking through doors and portals.
+// Each area is reported once.
+fn check_reachable(map: &Map, report: &mut Report) {
+    // Stepping into the cell in front of a portal carries on from the cell in front of the linked face
+    let mut portals: HashMap<(i32, i32), Vec<(i32, i32)>> = HashMap::new();
+    for (entry, exit) in &map.portals {
+        portals.entry(entry.front_cell()).or_default().push(exit.front_cell());
+    }
+    let flood = |start: (i32, i32), seen: &mut HashSet<(i32, i32)>| -> usize {
+        let mut queue = VecDeque::from([start]);
+        seen.insert(start);
+        let mut count = 0;
+        while let Some((col, row)) = queue.pop_front() {
+            count += 1;
+            let steps = NEIGHBOURS.iter().map(|&(dx, dy)| (col + dx, row + dy))
+                .chain(portals.get(&(col, row)).into_iter().flatten().copied());
+            for next in steps {
+                if walkable(map, next.0, next.1) && seen.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        count
+    };
+
+    let mut seen = HashSet::new();
+    let start = cell_of(map.spawn.x, map.spawn.y);
+    if walkable(map, start.0, start.1) {
+        flood(start, &mut seen);
+    }
+    for (row, cells) in map.cells.iter().enumerate() {
+        for col in 0..cells.len() {
+            let cell = (col as i32, row as i32);
+            if walkable(map, cell.0, cell.1) && !seen.contains(&cell) {
+                let count = flood(cell, &mut seen);
+                let cells = if count == 1 { "cell" } else { "cells" };
+                report.warnings.push(format!("{} open {} around ({}, {}) can't be reached from the player start", count, cells, cell.0, cell.1));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::PixelTexture;
+
+    // Stand-ins for every texture the test maps use
+    fn textures() -> TextureManager {
+        let mut texture_manager = TextureManager::empty();
+        for id in 1..=8 {
+            texture_manager.add_wall_texture(id, PixelTexture::solid(4, 4, [id * 30, 0, 0]));
+        }
+        texture_manager.add_texture("enemy_soldier", PixelTexture::solid(4, 4, [0, 0, 0]));
+        texture_manager
+    }
+
+    fn check(text: &str) -> Report {
+        validate(&Map::parse(text).unwrap(), &textures())
+    }
+
+    #[test]
+    fn closed_map_is_fine() {
+        let report = check("[grid]\n1 1 1 1\n1 0 0 1\n1 1 1 1\n[player]\nstart 1.5 1.5 0\n");
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+    }
+
+    #[test]
+    fn default_map_has_no_errors() {
+        let map = Map::new();
+        let mut texture_manager = textures();
+        for entity in &map.entities {
+            texture_manager.add_texture(&entity.texture_name, PixelTexture::solid(4, 4, [0, 0, 0]));
+        }
+        let report = validate(&map, &texture_manager);
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+    }
+
+    #[test]
+    fn mirror_and_portal_faces_need_no_texture() {
+        let mut texture_manager = TextureManager::empty();
+        texture_manager.add_wall_texture(1, PixelTexture::solid(4, 4, [0, 0, 0]));
+        texture_manager.add_wall_texture(4, PixelTexture::solid(4, 4, [0, 0, 0]));
+        let text = "[grid]\n1 1 1 1\n1 0 0 9\n1 2 1 1\n[walls]\nmirror 9 255 255 255\n\
+                    [cells]\nface 1 2 west 1\nface 1 2 east 1\nface 1 2 south 1\n\
+                    portal 1 2 north 0 1 east\n[player]\nstart 1.5 1.5 0\n";
+        let report = validate(&Map::parse(text).unwrap(), &texture_manager);
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+    }
+
+    #[test]
+    fn open_border() {
+        let report = check("[grid]\n1 1 1 1\n1 0 0 0\n1 1 1 1\n[player]\nstart 1.5 1.5 0\n");
+        assert_eq!(report.errors, ["cell (3, 1) is open to the edge of the map, the border has to be closed"]);
+    }
+
+    #[test]
+    fn start_in_a_wall() {
+        let report = check("[grid]\n1 1 1 1\n1 0 0 1\n1 1 1 1\n[player]\nstart 0.5 0.5 0\n");
+        assert!(report.errors.contains(&"the player starts inside the wall at (0, 0)".to_string()), "{:?}", report.errors);
+    }
+
+    #[test]
+    fn entity_in_a_wall() {
+        let report = check("[grid]\n1 1 1 1\n1 0 0 1\n1 1 1 1\n[player]\nstart 1.5 1.5 0\n\
+                            [entities]\nenemy_soldier 3.5 1.5\n");
+        assert_eq!(report.errors, ["enemy_soldier at (3.5, 1.5) is inside the solid cell (3, 1)"]);
+    }
+
+    #[test]
+    fn door_without_a_frame() {
+        let report = check("[grid]\n1 1 1 1 1\n1 0 0 0 1\n1 0 4 0 1\n1 0 0 0 1\n1 1 1 1 1\n\
+                            [cells]\ndoor 2 2 4\n[player]\nstart 1.5 1.5 0\n");
+        assert_eq!(report.errors, ["door at (2, 2) has no frame, it needs walls on both sides"]);
+    }
+
+    #[test]
+    fn unreachable_area() {
+        let report = check("[grid]\n1 1 1 1 1\n1 0 1 0 1\n1 0 1 0 1\n1 1 1 1 1\n[player]\nstart 1.5 1.5 0\n");
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(report.warnings, ["2 open cells around (3, 1) can't be reached from the player start"]);
+    }
+
+    #[test]
+    fn portal_reaches_the_other_side() {
+        let report = check("[grid]\n1 1 1 1 1\n1 0 1 0 1\n1 0 1 0 1\n1 1 1 1 1\n\
+                            [cells]\nportal 1 3 north 3 3 north\n[player]\nstart 1.5 1.5 0\n");
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+    }
+}